@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::walker::{Counter, Options};
+
+/// The version of the cache file, the caches of the other versions are discarded.
+const CACHE_VERSION: u64 = 2;
+
+/// The coarsest granularity of the times of the common filesystems (FAT).
+/// A directory changed within it before the scan may change again without changing its times,
+/// so it's not cached.
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+/// The direct counts of a directory, they are valid while its identity and times are unchanged.
+/// The contents of the files don't change the times of the directory, so the sizes are never cached.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct DirEntry {
+    // (dev, ino, mtime, mtime_nsec, ctime, ctime_nsec)
+    stamp: (u64, u64, i64, i64, i64, i64),
+    n_files: u64,
+    n_dirs: u64,
+    // the names of the sub-directories
+    sub_dirs: Vec<PathBuf>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheFile {
+    version: u64,
+    fingerprint: String,
+    // the canonical path of each directory -> its entry
+    dirs: HashMap<PathBuf, DirEntry>,
+}
+
+/// The on-disk cache of the direct counts of the traversed directories
+#[derive(Debug)]
+pub struct Cache {
+    path: PathBuf,
+    fingerprint: String,
+    // the entries loaded from the cache file
+    old: HashMap<PathBuf, DirEntry>,
+    // the entries of the directories traversed in this run
+    new: Mutex<HashMap<PathBuf, DirEntry>>,
+    // when the scan started
+    scan_time: SystemTime,
+}
+
+fn stamp(meta: &fs::Metadata) -> (u64, u64, i64, i64, i64, i64) {
+    return (
+        meta.dev(),
+        meta.ino(),
+        meta.mtime(),
+        meta.mtime_nsec(),
+        meta.ctime(),
+        meta.ctime_nsec(),
+    );
+}
+
+/// The options which change the direct counts, the cache is invalidated when they change.
+/// The sizes are never cached, so the size options are not part of it.
+pub fn fingerprint(opts: &Options) -> String {
+    let pattern = |re: &Option<regex::Regex>| re.as_ref().map(|re| re.as_str().to_string());
+    return format!(
+        "{:?}",
+        (
+            opts.with_hidden,
+            pattern(&opts.filter),
+            pattern(&opts.dir_filter),
+            pattern(&opts.exclude),
+        )
+    );
+}
+
+impl Cache {
+    /// The path of the cache file: `$XDG_CACHE_HOME/fcnt/dirs.json`,
+    /// `XDG_CACHE_HOME` defaults to `$HOME/.cache`.
+    pub fn default_path() -> Option<PathBuf> {
+        let cache_home = match env::var_os("XDG_CACHE_HOME").filter(|v| !v.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(env::var_os("HOME")?).join(".cache"),
+        };
+        return Some(cache_home.join("fcnt").join("dirs.json"));
+    }
+
+    /// Load the cache, it's empty if the file is missing or broken, or the fingerprint differs.
+    /// The old entries are dropped if `rebuild`.
+    pub fn load(path: &Path, fingerprint: String, rebuild: bool) -> Self {
+        let file = match rebuild {
+            true => CacheFile::default(),
+            false => fs::read_to_string(path)
+                .ok()
+                .and_then(|content| serde_json::from_str::<CacheFile>(&content).ok())
+                .filter(|f| f.version == CACHE_VERSION && f.fingerprint == fingerprint)
+                .unwrap_or_default(),
+        };
+        return Self {
+            path: path.to_path_buf(),
+            fingerprint,
+            old: file.dirs,
+            new: Mutex::new(HashMap::new()),
+            scan_time: SystemTime::now(),
+        };
+    }
+
+    /// Get the sub-directories and the direct counter of an unchanged directory by its canonical path,
+    /// they are joined to the path it's traversed by.
+    pub fn lookup(&self, key: &Path, dirpath: &Path, meta: &fs::Metadata) -> Option<(Vec<PathBuf>, Counter)> {
+        let entry = self.old.get(key).filter(|e| e.stamp == stamp(meta))?;
+        let mut cnt = Counter::new(dirpath, false);
+        cnt.n_files = entry.n_files;
+        cnt.n_dirs = entry.n_dirs;
+        let sub_dirs = Vec::from_iter(entry.sub_dirs.iter().map(|name| dirpath.join(name)));
+        self.new.lock().expect("acquire lock err").insert(key.to_path_buf(), entry.clone());
+        return Some((sub_dirs, cnt));
+    }
+
+    /// Store the sub-directories and the direct counter of a directory by its canonical path.
+    /// The directories changed just before the scan are skipped, see `RACY_WINDOW`.
+    pub fn store(&self, key: PathBuf, meta: &fs::Metadata, sub_dirs: &[PathBuf], cnt: &Counter) {
+        let changed = UNIX_EPOCH + Duration::from_secs(u64::try_from(meta.mtime().max(meta.ctime())).unwrap_or(0));
+        if changed + RACY_WINDOW > self.scan_time {
+            return;
+        }
+        let entry = DirEntry {
+            stamp: stamp(meta),
+            n_files: cnt.n_files,
+            n_dirs: cnt.n_dirs,
+            sub_dirs: Vec::from_iter(sub_dirs.iter().filter_map(|p| p.file_name()).map(PathBuf::from)),
+        };
+        self.new.lock().expect("acquire lock err").insert(key, entry);
+    }
+
+    /// Save the cache, the old entries inside the roots traversed `recursive`ly are replaced by the new ones,
+    /// so the deleted directories are dropped. Otherwise only the traversed roots themselves are replaced.
+    pub fn save(&self, roots: &[PathBuf], recursive: bool) -> io::Result<()> {
+        let roots = Vec::from_iter(roots.iter().filter_map(|root| fs::canonicalize(root).ok()));
+        let mut dirs = HashMap::from_iter(
+            self.old
+                .iter()
+                .filter(|(dir, _)| !recursive || !roots.iter().any(|root| dir.starts_with(root)))
+                .map(|(dir, entry)| (dir.clone(), entry.clone())),
+        );
+        dirs.extend(self.new.lock().expect("acquire lock err").drain());
+        let file = CacheFile {
+            version: CACHE_VERSION,
+            fingerprint: self.fingerprint.clone(),
+            dirs,
+        };
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // write to a temporary file first, so that a broken cache is never left behind
+        let tmp_path = self.path.with_extension(format!("tmp{}", std::process::id()));
+        fs::write(&tmp_path, serde_json::to_string(&file)?)?;
+        return fs::rename(&tmp_path, &self.path);
+    }
+}
+
+#[test]
+fn test_cache() {
+    let tmp = crate::testutil::TempDir::new("cache");
+    let root = tmp.path();
+    let cache_path = root.join("cache/dirs.json");
+    fs::create_dir_all(root.join("d")).unwrap();
+    let dir = root.join("d");
+    let key = fs::canonicalize(&dir).unwrap();
+
+    let meta = fs::metadata(&dir).unwrap();
+    let mut cnt = Counter::new(&dir, false);
+    cnt.n_files = 3;
+    // the directory has just been made, so it may change again in the same tick
+    let cache = Cache::load(&cache_path, String::from("fp"), false);
+    cache.store(key.clone(), &meta, &[dir.join("sub")], &cnt);
+    cache.save(&[root.to_path_buf()], true).unwrap();
+    let mut cache = Cache::load(&cache_path, String::from("fp"), false);
+    assert!(cache.lookup(&key, &dir, &meta).is_none());
+
+    // the scan starts well after the change
+    cache.scan_time += RACY_WINDOW * 2;
+    cache.store(key.clone(), &meta, &[dir.join("sub")], &cnt);
+    cache.save(&[root.to_path_buf()], true).unwrap();
+
+    let cache = Cache::load(&cache_path, String::from("fp"), false);
+    let (sub_dirs, cnt) = cache.lookup(&key, &dir, &meta).unwrap();
+    assert_eq!((sub_dirs, cnt.n_files), (vec![dir.join("sub")], 3));
+
+    // the same directory is found by another path, and its sub-directories follow that path
+    let other = root.join("d/../d");
+    let (sub_dirs, cnt) = cache.lookup(&key, &other, &meta).unwrap();
+    assert_eq!((sub_dirs, cnt.dirpath), (vec![other.join("sub")], Counter::new(&other, false).dirpath));
+
+    // the directory is changed
+    fs::write(dir.join("f"), "").unwrap();
+    assert!(cache.lookup(&key, &dir, &fs::metadata(&dir).unwrap()).is_none());
+
+    // the non-recursive walk of the root keeps the entries inside it
+    Cache::load(&cache_path, String::from("fp"), false).save(&[root.to_path_buf()], false).unwrap();
+    assert!(Cache::load(&cache_path, String::from("fp"), false).lookup(&key, &dir, &meta).is_some());
+
+    // the options are changed, or the cache is rebuilt
+    assert!(Cache::load(&cache_path, String::from("other"), false).lookup(&key, &dir, &meta).is_none());
+    assert!(Cache::load(&cache_path, String::from("fp"), true).lookup(&key, &dir, &meta).is_none());
+}
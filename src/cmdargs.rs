@@ -1,64 +1,214 @@
-use std::path::{PathBuf, MAIN_SEPARATOR};
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::iter;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 
-use clap::{Parser, ValueEnum};
-use num_cpus;
+use clap::{ArgAction, ArgGroup, CommandFactory, Parser, ValueEnum};
+use clap_complete::Shell;
 use regex::Regex;
+use serde_json::{json, Value};
 
-use crate::output::print_err;
-
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
-pub enum OrderBy {
-    /// order by pathname
-    Name,
-    /// alias of Name
-    N,
-    /// order by number of files
-    File,
-    /// alias of File
-    F,
-    /// order by number of directories
-    Dir,
-    /// alias of Dir
-    D,
-    /// order by size of each directory
-    Size,
-    /// alias of Size
-    S,
+use crate::config::Config;
+use crate::output::{locale_digit_sep, print_err, SizeFormat, Theme};
+use crate::snapshot::Snapshot;
+use crate::table::Column;
+use crate::template::Template;
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// aligned table for the terminal
+    Table,
+    /// JSON document
+    Json,
+    /// newline-delimited JSON, one object per line
+    Ndjson,
+    /// comma-separated values
+    Csv,
+    /// tab-separated values
+    Tsv,
+    /// GitHub pipe table
+    Markdown,
+    /// standalone HTML page with a sortable table
+    Html,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum ColorMode {
+    /// colour the output only when it's a terminal
+    Auto,
+    /// always colour the output
+    Always,
+    /// never colour the output
+    Never,
+}
+
+/// Load a snapshot, exit if it fails
+pub fn load_snapshot(path: &Path) -> Snapshot {
+    match Snapshot::load(path) {
+        Ok(snapshot) => return snapshot,
+        Err(err) => {
+            print_err(&err, &path.display());
+            exit(1);
+        }
+    }
 }
 
 #[derive(Parser)]
 #[command(name = "fcnt")]
-#[command(version = "0.2.7")]
+#[command(version)]
 #[command(about = "Count the total number of files in given directories.")]
+#[command(args_override_self = true)]
+#[command(group(ArgGroup::new("nul_paths").args(["list", "from_file"]).multiple(true)))]
 pub struct CmdArgParser {
     /// The directories (default: ./).
     pub directories: Vec<String>,
 
+    /// Read the directories from a file, or from stdin if it's "-".
+    /// They are separated by newlines, or by NULs with `-0`, and counted as they are read.
+    #[arg(long, value_name = "FILE")]
+    pub from_file: Option<String>,
+
     /// Count all regular and hidden files.
-    #[arg(short = 'a')]
+    #[arg(short = 'a', overrides_with = "no_all")]
     pub all_files: bool,
 
+    /// Ignore the hidden files, it negates `-a` in the defaults.
+    #[arg(long)]
+    pub no_all: bool,
+
     /// Count the number of directories.
-    #[arg(short = 'd')]
+    #[arg(short = 'd', overrides_with = "no_dirs")]
     pub with_dir: bool,
 
-    /// The value to sort the results by.
-    #[arg(short = 'o', value_enum)]
-    pub order_by: Option<OrderBy>,
+    /// Don't count the number of directories, it negates `-d` in the defaults,
+    /// and the Dirs column implied by `-o`, `--dir-filter` and `--columns`.
+    #[arg(long)]
+    pub no_dirs: bool,
+
+    /// Exclude the nested directories from the figures of their parent directories.
+    #[arg(long, overrides_with = "no_exclude_nested")]
+    pub exclude_nested: bool,
+
+    /// Include the nested directories in the figures of their parent directories,
+    /// it negates `--exclude-nested` in the defaults.
+    #[arg(long)]
+    pub no_exclude_nested: bool,
+
+    /// The columns to sort the results by, separated by commas, such as "size,path".
+    /// The aliases [n]ame, [f]ile, [d]ir and [s]ize are accepted too.
+    #[arg(short = 'o', value_enum, value_delimiter = ',', value_name = "ORDER_BY", action = ArgAction::Set,
+          overrides_with = "no_order")]
+    pub order_by: Vec<Column>,
+
+    /// Keep the results in the order of the directories, it negates `-o` in the defaults.
+    #[arg(long)]
+    pub no_order: bool,
+
+    /// Reverse the order of the results.
+    #[arg(long, overrides_with = "no_reverse")]
+    pub reverse: bool,
+
+    /// Don't reverse the order of the results, it negates `--reverse` in the defaults.
+    #[arg(long)]
+    pub no_reverse: bool,
+
+    /// Only display the first N results, the others are collapsed into one row.
+    /// 0 displays all of them, it negates `--limit` in the defaults.
+    #[arg(long, value_name = "N")]
+    pub limit: Option<usize>,
 
     /// Match entries using regex (only matche filenames).
     #[arg(short = 'r', value_name = "PATTERN")]
     pub re: Option<String>,
 
+    /// Match directories using regex (only matche dirnames), it doesn't affect the traversal.
+    #[arg(long, value_name = "PATTERN")]
+    pub dir_filter: Option<String>,
+
+    /// Ignore the files and dirs matching the patterns of the named sets in the config file,
+    /// separated by commas. The ignored dirs are not traversed.
+    #[arg(long, value_delimiter = ',', value_name = "NAME", action = ArgAction::Set)]
+    pub exclude_set: Vec<String>,
+
     /// Non-recursive mode (files in sub-directories will be ignored).
-    #[arg(short = 'R')]
+    #[arg(short = 'R', overrides_with = "recursive")]
     pub non_recursive: bool,
 
+    /// Recursive mode, it negates `-R` in the defaults.
+    #[arg(long)]
+    pub recursive: bool,
+
     /// Count the total size of files.
-    #[arg(short = 's')]
+    #[arg(short = 's', overrides_with = "no_size")]
     pub with_size: bool,
 
+    /// Don't count the size of files, it negates `-s` in the defaults,
+    /// and the sizes implied by `-o` and `--columns`.
+    #[arg(long)]
+    pub no_size: bool,
+
+    /// Count the apparent size of files rather than the disk usage.
+    #[arg(long, overrides_with = "no_apparent_size")]
+    pub apparent_size: bool,
+
+    /// Count the disk usage of files, it negates `--apparent-size` in the defaults.
+    #[arg(long)]
+    pub no_apparent_size: bool,
+
+    /// Display the sizes in powers of 1000 rather than 1024.
+    #[arg(long, conflicts_with_all = ["bytes", "block_size"], overrides_with = "no_si")]
+    pub si: bool,
+
+    /// Display the sizes in powers of 1024, it negates `--si` in the defaults.
+    #[arg(long)]
+    pub no_si: bool,
+
+    /// Display the exact sizes in bytes, it implies `--apparent-size`.
+    #[arg(long, conflicts_with = "block_size")]
+    pub bytes: bool,
+
+    /// Display the sizes in units of K, M, G, T (powers of 1024) or KB, MB, GB, TB (powers of 1000).
+    #[arg(long, value_name = "SIZE", value_parser = SizeFormat::parse_block_size)]
+    pub block_size: Option<SizeFormat>,
+
+    /// The number of decimals of the human-readable sizes.
+    #[arg(long, value_name = "N", conflicts_with_all = ["bytes", "block_size"])]
+    pub precision: Option<usize>,
+
+    /// Group the digits of the counts, such as "17,154,321", the separator follows `LC_ALL` or `LC_NUMERIC`.
+    /// It's enabled automatically if one of them is set to a known language.
+    #[arg(long, overrides_with = "no_group_digits")]
+    pub group_digits: bool,
+
+    /// Don't group the digits of the counts, even if the locale is set.
+    #[arg(long)]
+    pub no_group_digits: bool,
+
+    /// The columns of the table and their order, separated by commas, such as "path,size,files".
+    /// It overrides `-d`, `-p` and `--bar`.
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "COLUMNS", action = ArgAction::Set)]
+    pub columns: Vec<Column>,
+
+    /// Display the percentage of each row in the Total, "%Size" is displayed along with `-s`.
+    #[arg(short = 'p', long, overrides_with = "no_percent")]
+    pub percent: bool,
+
+    /// Don't display the percentages, it negates `-p` in the defaults.
+    #[arg(long)]
+    pub no_percent: bool,
+
+    /// Display the share of each row in the Total as a bar, by size along with `-s`, otherwise by files.
+    #[arg(long, overrides_with = "no_bar")]
+    pub bar: bool,
+
+    /// Don't display the bars, it negates `--bar` in the defaults.
+    #[arg(long)]
+    pub no_bar: bool,
+
     /// The number of threads for traversal (invalid in `non_recursive` mode).
     #[arg(short = 't', value_name = "TOP")]
     pub top_n: Option<usize>,
@@ -67,14 +217,230 @@ pub struct CmdArgParser {
     #[arg(short = 'T', value_name = "THREAD_NUM")]
     pub n_thread: Option<usize>,
 
+    /// Display the directories as trees, with the percentage of their parents and the bars.
+    #[arg(long, conflicts_with = "format", overrides_with = "no_tree")]
+    pub tree: bool,
+
+    /// Display the roots as a table, it negates `--tree` in the defaults.
+    #[arg(long)]
+    pub no_tree: bool,
+
+    /// The max depth of the directories in the tree view.
+    #[arg(long, value_name = "N", requires = "tree")]
+    pub depth: Option<usize>,
+
+    /// Collapse the directories whose share of their parent is less than PCT in the tree view.
+    #[arg(long, value_name = "PCT", default_value_t = 1.0, requires = "tree")]
+    pub min_percent: f64,
+
+    /// The output format, the machine-readable formats use raw integers and bytes.
+    #[arg(long, value_enum, default_value = "table")]
+    pub format: Format,
+
+    /// When to colour the output, `NO_COLOR` and `CLICOLOR_FORCE` are respected in auto mode.
+    #[arg(long, value_enum, value_name = "WHEN", default_value = "auto")]
+    pub color: ColorMode,
+
+    /// The colour theme, defined in the config file or built-in: default, light.
+    #[arg(long, value_name = "NAME")]
+    pub theme: Option<String>,
+
+    /// Colour the paths of the found entries by `LS_COLORS`, like `ls` does.
+    #[arg(long)]
+    pub ls_colors: bool,
+
     /// Verbose mode, open this option will display the found entries.
     #[arg(short = 'v')]
     pub verbose: bool,
+
+    /// Display the found entries with a template, such as "{size}\t{path}\n".
+    /// Placeholders: {path}, {relpath}, {name}, {ext}, {size}, {mtime}, {mode}, {uid}, {inode}, {type}.
+    #[arg(long, value_name = "TEMPLATE")]
+    pub printf: Option<String>,
+
+    /// List the paths of the matched files only, instead of the table.
+    #[arg(long)]
+    pub list: bool,
+
+    /// Cache the counts of each directory, and reuse them while the directory is unchanged.
+    #[arg(long, overrides_with = "no_cache")]
+    pub cache: bool,
+
+    /// Don't use the cache of the directory counts, it negates `--cache` in the defaults.
+    #[arg(long, conflicts_with = "rebuild_cache")]
+    pub no_cache: bool,
+
+    /// Discard the cache of the directory counts, and build it again, it implies `--cache`.
+    #[arg(long)]
+    pub rebuild_cache: bool,
+
+    /// Keep counting the directories, the table is redrawn when the files change (Linux only).
+    #[arg(long, conflicts_with_all = ["list", "printf", "verbose", "tree", "compare"])]
+    pub watch: bool,
+
+    /// Save the counters to a snapshot file, along with the counters of each directory in the tree view.
+    #[arg(long, value_name = "FILE")]
+    pub save: Option<PathBuf>,
+
+    /// Compare the counters with a snapshot file saved by `--save`, and display the changes.
+    #[arg(long, value_name = "FILE")]
+    pub compare: Option<PathBuf>,
+
+    /// Compare two snapshot files saved by `--save` without counting, and display the changes.
+    #[arg(long, num_args = 2, value_names = ["OLD", "NEW"], action = ArgAction::Set,
+          conflicts_with_all = ["compare", "save", "watch"])]
+    pub diff: Vec<PathBuf>,
+
+    /// Separate the paths by NUL instead of newline: the paths listed by `--list`,
+    /// and the paths read by `--from-file`.
+    #[arg(short = '0', requires = "nul_paths")]
+    pub null: bool,
+
+    /// Print the completion script for a shell, and exit.
+    #[arg(long, value_enum, value_name = "SHELL", hide = true)]
+    pub generate_completions: Option<Shell>,
+
+    /// Print the man page, and exit.
+    #[arg(long, hide = true)]
+    pub generate_manpage: bool,
 }
 
 impl CmdArgParser {
-    pub fn get_regex(&self) -> Option<Regex> {
-        if let Some(re) = &self.re {
+    /// Parse the command line, the default options are prepended to it:
+    /// the `options` in the config file first, then the `FCNT_OPTS` environment variable.
+    /// The later ones win, so the command line overrides the defaults.
+    /// `FCNT_OPTS` is split on whitespace and the quotes are not interpreted.
+    pub fn parse_with_defaults(config: &Config) -> Self {
+        let mut args = Vec::from_iter(env::args_os());
+        let cmdline = args.split_off(args.len().min(1));
+        args.extend(config.options.iter().map(OsString::from));
+        if let Some(opts) = env::var_os("FCNT_OPTS") {
+            args.extend(
+                opts.to_string_lossy()
+                    .split_whitespace()
+                    .map(OsString::from),
+            );
+        }
+        args.extend(cmdline);
+        return Self::parse_from(args);
+    }
+
+    /// Print the completion script or the man page if they are asked for, return whether anything was printed.
+    /// They are generated from the definitions of the arguments.
+    pub fn generate(&self) -> bool {
+        let mut cmd = Self::command();
+        if let Some(shell) = self.generate_completions {
+            clap_complete::generate(shell, &mut cmd, "fcnt", &mut io::stdout());
+            return true;
+        } else if self.generate_manpage {
+            if let Err(err) = clap_mangen::Man::new(cmd).render(&mut io::stdout()) {
+                print_err(&err, &"manpage");
+                exit(1);
+            }
+            return true;
+        }
+        return false;
+    }
+
+    /// Whether the size of files should be counted, `--no-size` wins over the implied ones
+    pub fn need_size(&self) -> bool {
+        if self.no_size {
+            return false;
+        }
+        return self.with_size
+            || self.columns.contains(&Column::Size)
+            || self.columns.contains(&Column::PctSize)
+            || self.order_by.contains(&Column::Size)
+            || self.order_by.contains(&Column::PctSize);
+    }
+
+    /// How to render the sizes
+    pub fn get_size_format(&self) -> SizeFormat {
+        if self.bytes {
+            return SizeFormat::bytes();
+        } else if let Some(block_size) = &self.block_size {
+            return block_size.clone();
+        } else {
+            return SizeFormat::human(self.si, self.precision);
+        }
+    }
+
+    /// The number of the displayed results, None for all of them
+    pub fn get_limit(&self) -> Option<usize> {
+        return self.limit.filter(|&n| n > 0);
+    }
+
+    /// The thousands separator of the counts
+    pub fn get_digit_sep(&self) -> Option<char> {
+        if self.no_group_digits {
+            return None;
+        }
+        match locale_digit_sep() {
+            Some(sep) => return Some(sep),
+            None if self.group_digits => return Some(','),
+            None => return None,
+        }
+    }
+
+    // whether the number of directories should be displayed by default,
+    // `--no-dirs` wins over the implied ones
+    fn need_dir(&self) -> bool {
+        if self.no_dirs {
+            return false;
+        }
+        return self.with_dir || self.dir_filter.is_some() || self.order_by.contains(&Column::Dirs);
+    }
+
+    /// The columns of the table, the default ones are chosen by `-d`, `-s`, `-p` and `--bar`
+    pub fn get_columns(&self) -> Vec<Column> {
+        if !self.columns.is_empty() {
+            let dropped = |col: &Column| match col {
+                Column::Dirs => self.no_dirs,
+                Column::Size | Column::PctSize => self.no_size,
+                _ => false,
+            };
+            return Vec::from_iter(self.columns.iter().copied().filter(|col| !dropped(col)));
+        }
+        let mut columns = vec![Column::Path, Column::Files];
+        if self.need_dir() {
+            columns.push(Column::Dirs);
+        }
+        if self.need_size() {
+            columns.push(Column::Size);
+        }
+        if self.percent {
+            columns.push(Column::PctFiles);
+            if self.need_size() {
+                columns.push(Column::PctSize);
+            }
+        }
+        if self.bar {
+            columns.push(Column::Bar);
+        }
+        return columns;
+    }
+
+    /// The options in effect, used by the machine-readable formats
+    pub fn options_json(&self) -> Value {
+        return json!({
+            "all_files": self.all_files,
+            "with_dir": self.get_columns().contains(&Column::Dirs),
+            "with_size": self.need_size(),
+            "columns": Vec::from_iter(self.get_columns().iter().map(|c| c.name())),
+            "apparent_size": self.apparent_size || self.bytes,
+            "regex": self.re,
+            "dir_filter": self.dir_filter,
+            "non_recursive": self.non_recursive,
+            "exclude_nested": self.exclude_nested,
+            "exclude_set": self.exclude_set,
+            "order_by": Vec::from_iter(self.order_by.iter().map(|c| c.name())),
+            "reverse": self.reverse,
+            "limit": self.get_limit(),
+        });
+    }
+
+    fn compile_regex(re: &Option<String>) -> Option<Regex> {
+        if let Some(re) = re {
             match Regex::new(re.as_str()) {
                 Ok(filter) => return Some(filter),
                 Err(err) => {
@@ -82,17 +448,57 @@ impl CmdArgParser {
                     exit(1);
                 }
             }
-            // if let Ok(filter) = Regex::new(re.as_str()) {
-            //     return Some(filter);
-            // } else {
-            //     println!("{}", err(&format!("Involid regex pattern: {}", re)));
-            //     exit(1);
-            // }
         } else {
             return None;
         }
     }
 
+    pub fn get_regex(&self) -> Option<Regex> {
+        return Self::compile_regex(&self.re);
+    }
+
+    pub fn get_dir_regex(&self) -> Option<Regex> {
+        return Self::compile_regex(&self.dir_filter);
+    }
+
+    pub fn get_exclude(&self, config: &Config) -> Option<Regex> {
+        match config.get_exclude(&self.exclude_set) {
+            Ok(exclude) => return exclude,
+            Err(err) => {
+                print_err(&err, &format!("exclude set {}", self.exclude_set.join(",")));
+                exit(1);
+            }
+        }
+    }
+
+    pub fn get_template(&self) -> Option<Template> {
+        if self.list && self.printf.is_none() {
+            let printf = if self.null { "{path}\\0" } else { "{path}\\n" };
+            return Template::parse(printf).ok();
+        } else if let Some(printf) = &self.printf {
+            match Template::parse(printf) {
+                Ok(template) => return Some(template),
+                Err(err) => {
+                    print_err(&err, &printf);
+                    exit(1);
+                }
+            }
+        } else {
+            return None;
+        }
+    }
+
+    pub fn get_theme(&self, config: &Config) -> Theme {
+        match config.get_theme(self.theme.as_deref()) {
+            Ok(theme) => return theme,
+            Err(err) => {
+                let name = self.theme.as_deref().or(config.theme.as_deref());
+                print_err(&err, &format!("theme {}", name.unwrap_or_default()));
+                exit(1);
+            }
+        }
+    }
+
     pub fn get_threads_num(&self) -> usize {
         match self.n_thread {
             Some(num) => return num,
@@ -103,22 +509,230 @@ impl CmdArgParser {
         }
     }
 
-    pub fn get_directories(&self) -> Vec<PathBuf> {
-        if self.directories.is_empty() {
-            vec![PathBuf::from(".")]
-        } else {
-            self.directories
-                .iter()
-                .map(|p| {
-                    let len = p.len();
-                    if len > 1 {
-                        PathBuf::from(p.trim_end_matches(MAIN_SEPARATOR))
-                    } else {
-                        PathBuf::from(p)
-                    }
-                })
-                .filter(|p| p.is_dir())
-                .collect()
+    // read the directories listed in `--from-file` lazily, a read error is fatal
+    fn read_from_file(&self) -> Box<dyn Iterator<Item = PathBuf>> {
+        let Some(file) = self.from_file.clone() else {
+            return Box::new(iter::empty());
+        };
+        let reader: Box<dyn BufRead> = match file.as_str() {
+            "-" => Box::new(io::stdin().lock()),
+            _ => match File::open(&file) {
+                Ok(f) => Box::new(BufReader::new(f)),
+                Err(err) => {
+                    print_err(&err, &file);
+                    exit(1);
+                }
+            },
+        };
+        let sep = if self.null { b'\0' } else { b'\n' };
+        return Box::new(read_paths(reader, sep).map(move |result| match result {
+            Ok(path) => path,
+            Err(err) => {
+                print_err(&err, &file);
+                exit(1);
+            }
+        }));
+    }
+
+    /// Get the roots to traverse, they are checked by the walk.
+    /// The ones in `--from-file` are read as the walk goes.
+    pub fn get_directories(&self) -> Box<dyn Iterator<Item = PathBuf>> {
+        let directories = Vec::from_iter(self.directories.iter().map(PathBuf::from));
+        if directories.is_empty() && self.from_file.is_none() {
+            return Box::new(iter::once(PathBuf::from(".")));
         }
+        return Box::new(directories.into_iter().chain(self.read_from_file()).map(|p| normalize_path(&p)));
+    }
+}
+
+/// Read the paths separated by `sep`, they are parsed as the input is read, and the empty ones are skipped.
+/// The carriage return before each newline is dropped.
+pub fn read_paths(reader: impl BufRead, sep: u8) -> impl Iterator<Item = io::Result<PathBuf>> {
+    return reader.split(sep).filter_map(move |item| {
+        let item = match item {
+            Ok(item) => item,
+            Err(err) => return Some(Err(err)),
+        };
+        let path = match sep {
+            b'\n' => item.strip_suffix(b"\r").unwrap_or(&item),
+            _ => &item,
+        };
+        return match path.is_empty() {
+            true => None,
+            false => Some(Ok(PathBuf::from(OsStr::from_bytes(path)))),
+        };
+    });
+}
+
+/// Normalize a path by its components: drop the trailing and repeated separators and the inner `.`.
+/// The leading `./` is kept as typed, and so is `..`, since the component before it may be a symlink.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let normalized = PathBuf::from_iter(path.components());
+    if normalized.as_os_str().is_empty() {
+        return PathBuf::from(".");
+    }
+    return normalized;
+}
+
+#[test]
+fn test_normalize_path() {
+    let cases = [
+        ("data/", "data"),
+        ("data2///", "data2"),
+        ("/", "/"),
+        ("//", "/"),
+        (".", "."),
+        ("./", "."),
+        ("./a/./b/", "./a/b"),
+        ("a//b/.", "a/b"),
+        ("a/b/..", "a/b/.."),
+        ("../a", "../a"),
+        ("/usr/../etc/", "/usr/../etc"),
+    ];
+    for (path, expected) in cases {
+        assert_eq!(normalize_path(Path::new(path)), PathBuf::from(expected));
     }
 }
+
+#[test]
+fn test_override_defaults() {
+    let args = CmdArgParser::parse_from([
+        "fcnt",
+        "-a",
+        "-s",
+        "-o",
+        "size,dir",
+        "--no-size",
+        "-o",
+        "name",
+        "--no-all",
+    ]);
+    assert!(!args.all_files);
+    assert!(!args.need_size());
+    assert_eq!(args.order_by, [Column::Path]);
+
+    // the negations win over the implied sizes and dirs
+    let args = CmdArgParser::parse_from([
+        "fcnt",
+        "-s",
+        "-o",
+        "s,d",
+        "--columns",
+        "path,size,dirs,files",
+        "--no-size",
+        "--no-dirs",
+    ]);
+    assert!(!args.need_size());
+    assert_eq!(args.get_columns(), [Column::Path, Column::Files]);
+    let args = CmdArgParser::parse_from([
+        "fcnt",
+        "-o",
+        "s",
+        "--dir-filter",
+        "x",
+        "--no-size",
+        "--no-dirs",
+    ]);
+    assert_eq!(args.get_columns(), [Column::Path, Column::Files]);
+
+    let args = CmdArgParser::parse_from([
+        "fcnt",
+        "--no-dirs",
+        "-d",
+        "--exclude-set",
+        "a,b",
+        "--exclude-set",
+        "c",
+    ]);
+    assert!(args.with_dir);
+    assert_eq!(args.exclude_set, ["c"]);
+
+    let args = CmdArgParser::parse_from(["fcnt", "--group-digits", "--no-group-digits"]);
+    assert_eq!(args.get_digit_sep(), None);
+
+    // every flag in the defaults can be negated, and the value options can be reset
+    let args = CmdArgParser::parse_from([
+        "fcnt",
+        "--reverse",
+        "-R",
+        "--tree",
+        "-p",
+        "--bar",
+        "--exclude-nested",
+        "--apparent-size",
+        "--si",
+        "-o",
+        "size",
+        "--limit",
+        "5",
+        "--no-reverse",
+        "--recursive",
+        "--no-tree",
+        "--no-percent",
+        "--no-bar",
+        "--no-exclude-nested",
+        "--no-apparent-size",
+        "--no-si",
+        "--no-order",
+        "--limit",
+        "0",
+    ]);
+    assert!(!(args.reverse || args.non_recursive || args.tree || args.percent || args.bar));
+    assert!(!(args.exclude_nested || args.apparent_size || args.si));
+    assert!(args.order_by.is_empty() && !args.need_size());
+    assert_eq!(args.get_columns(), [Column::Path, Column::Files]);
+    assert_eq!(args.get_limit(), None);
+
+    // and the later flags win again
+    let args = CmdArgParser::parse_from(["fcnt", "--no-order", "--no-tree", "-o", "dirs", "--tree"]);
+    assert_eq!((args.order_by.as_slice(), args.tree), ([Column::Dirs].as_slice(), true));
+    assert_eq!(CmdArgParser::parse_from(["fcnt", "--limit", "3"]).get_limit(), Some(3));
+}
+
+#[test]
+fn test_diff_args() {
+    let args = CmdArgParser::parse_from(["fcnt", "--diff", "old.json", "new.json"]);
+    assert_eq!(args.diff, [Path::new("old.json"), Path::new("new.json")]);
+
+    // the words like "diff" are the directories
+    let args = CmdArgParser::parse_from(["fcnt", "diff", "manpage"]);
+    assert_eq!(args.directories, ["diff", "manpage"]);
+    assert!(args.diff.is_empty());
+}
+
+#[test]
+fn test_generate() {
+    let mut cmd = CmdArgParser::command();
+    let mut script = vec![];
+    clap_complete::generate(Shell::Fish, &mut cmd, "fcnt", &mut script);
+    let script = String::from_utf8(script).unwrap();
+    assert!(script.contains("the number of files"));
+
+    let mut page = vec![];
+    clap_mangen::Man::new(CmdArgParser::command()).render(&mut page).unwrap();
+    let page = String::from_utf8(page).unwrap();
+    assert!(page.contains("exclude\\-set"));
+    assert!(page.contains(env!("CARGO_PKG_VERSION")));
+
+    // they are the maintenance flags, so they are hidden from the help
+    let help = CmdArgParser::command().render_long_help().to_string();
+    assert!(help.contains("--exclude-set") && !help.contains("--generate-"));
+    let args = CmdArgParser::parse_from(["fcnt", "--generate-completions", "bash"]);
+    assert_eq!(args.generate_completions, Some(Shell::Bash));
+}
+
+#[test]
+fn test_read_paths() {
+    let read = |input: &'static str, sep| Vec::from_iter(read_paths(input.as_bytes(), sep).map(Result::unwrap));
+    assert_eq!(read("a\nb c\r\n\n/d/\n", b'\n'), [Path::new("a"), Path::new("b c"), Path::new("/d/")]);
+
+    // the separator is never guessed from the input
+    assert_eq!(read("a\nb\0c\0", b'\0'), [Path::new("a\nb"), Path::new("c")]);
+    assert_eq!(read("a\0b\n", b'\n'), [Path::new("a\0b")]);
+    assert!(read("", b'\n').is_empty());
+
+    // `-0` is for the listed paths or the paths read from a file
+    let args = CmdArgParser::parse_from(["fcnt", "--from-file", "-", "-0"]);
+    assert!(args.null);
+    assert!(CmdArgParser::try_parse_from(["fcnt", "-0"]).is_err());
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::output::{Style, Theme};
+
+#[derive(Debug)]
+pub struct ConfigError {
+    msg: String,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl Error for ConfigError {}
+
+/// The styles of a theme in the config file, the missing roles use the default theme.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThemeConfig {
+    pub title: Option<String>,
+    pub total: Option<String>,
+    pub file: Option<String>,
+    pub dir: Option<String>,
+    pub symlink: Option<String>,
+    pub error: Option<String>,
+}
+
+/// The config file, such as:
+///
+/// ```toml
+/// options = ["-a", "-s", "-o", "size"]
+/// theme = "mine"
+///
+/// [exclude_sets]
+/// python = ["^__pycache__$", "\\.pyc$"]
+///
+/// [themes.mine]
+/// title = "blue underline"
+/// file = "color(30)"
+/// dir = "#af5f00 bold"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// the default options, they are overridden by `FCNT_OPTS` and the command line
+    #[serde(default)]
+    pub options: Vec<String>,
+    /// the name of the theme in use
+    pub theme: Option<String>,
+    #[serde(default)]
+    pub themes: HashMap<String, ThemeConfig>,
+    /// the named sets of patterns, the matched files and dirs are ignored
+    #[serde(default)]
+    pub exclude_sets: HashMap<String, Vec<String>>,
+}
+
+impl Config {
+    /// The path of the config file: `$XDG_CONFIG_HOME/fcnt/config.toml`,
+    /// `XDG_CONFIG_HOME` defaults to `$HOME/.config`.
+    pub fn path() -> Option<PathBuf> {
+        let config_home = match env::var_os("XDG_CONFIG_HOME").filter(|v| !v.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        return Some(config_home.join("fcnt").join("config.toml"));
+    }
+
+    pub fn parse(content: &str) -> Result<Self, ConfigError> {
+        return toml::from_str(content).map_err(|err| ConfigError {
+            msg: err.message().to_string(),
+        });
+    }
+
+    /// Load the config file, it's empty if the file doesn't exist.
+    pub fn load() -> Result<Self, ConfigError> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(content) => Self::parse(&content),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(ConfigError {
+                msg: err.to_string(),
+            }),
+        }
+    }
+
+    /// Get a theme by name, the themes in the config file take precedence over the built-in ones.
+    /// The theme set in the config file is used if `name` is None.
+    pub fn get_theme(&self, name: Option<&str>) -> Result<Theme, ConfigError> {
+        let Some(name) = name.or(self.theme.as_deref()) else {
+            return Ok(Theme::default());
+        };
+        let Some(conf) = self.themes.get(name) else {
+            return Theme::builtin(name).ok_or_else(|| ConfigError {
+                msg: String::from("unknown theme"),
+            });
+        };
+
+        let mut theme = Theme::default();
+        let roles = [
+            (&conf.title, &mut theme.title),
+            (&conf.total, &mut theme.total),
+            (&conf.file, &mut theme.file),
+            (&conf.dir, &mut theme.dir),
+            (&conf.symlink, &mut theme.symlink),
+            (&conf.error, &mut theme.error),
+        ];
+        for (spec, style) in roles {
+            if let Some(spec) = spec {
+                *style = Style::parse(spec).map_err(|msg| ConfigError { msg })?;
+            }
+        }
+        return Ok(theme);
+    }
+
+    /// Join the patterns of the named exclude sets into one regex, None if there is no pattern.
+    pub fn get_exclude(&self, names: &[String]) -> Result<Option<Regex>, ConfigError> {
+        let mut patterns = vec![];
+        for name in names {
+            let Some(set) = self.exclude_sets.get(name) else {
+                return Err(ConfigError {
+                    msg: String::from("unknown exclude set"),
+                });
+            };
+            patterns.extend(set.iter().map(|p| format!("(?:{})", p)));
+        }
+        if patterns.is_empty() {
+            return Ok(None);
+        }
+        return Regex::new(&patterns.join("|"))
+            .map(Some)
+            .map_err(|err| ConfigError {
+                msg: err.to_string(),
+            });
+    }
+}
+
+#[test]
+fn test_theme_config() {
+    let config = Config::parse(
+        r##"
+        theme = "mine"
+
+        [themes.mine]
+        title = "blue underline"
+        dir = "#af5f00 bold"
+
+        [themes.broken]
+        file = "pink"
+        "##,
+    )
+    .unwrap();
+
+    let theme = config.get_theme(None).unwrap();
+    assert_eq!(theme.title, Style::parse("blue underline").unwrap());
+    assert_eq!(theme.dir, Style::parse("#af5f00 bold").unwrap());
+    assert_eq!(theme.file, Theme::default().file);
+
+    assert_eq!(config.get_theme(Some("light")).unwrap(), Theme::builtin("light").unwrap());
+    assert!(config.get_theme(Some("broken")).is_err());
+    assert!(config.get_theme(Some("unknown")).is_err());
+    assert!(Config::parse("unknown = 1").is_err());
+    assert_eq!(Config::default().get_theme(None).unwrap(), Theme::default());
+}
+
+#[test]
+fn test_exclude_sets() {
+    let config = Config::parse(
+        r#"
+        options = ["-a", "-s"]
+
+        [exclude_sets]
+        python = ["^__pycache__$", "\\.pyc$"]
+        git = ["^\\.git$"]
+        broken = ["("]
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.options, ["-a", "-s"]);
+
+    let names = |names: &[&str]| Vec::from_iter(names.iter().map(|n| n.to_string()));
+    let exclude = config
+        .get_exclude(&names(&["python", "git"]))
+        .unwrap()
+        .unwrap();
+    assert!(exclude.is_match("__pycache__"));
+    assert!(exclude.is_match("a.pyc"));
+    assert!(exclude.is_match(".git"));
+    assert!(!exclude.is_match("a.py"));
+
+    assert!(config.get_exclude(&[]).unwrap().is_none());
+    assert!(config.get_exclude(&names(&["unknown"])).is_err());
+    assert!(config.get_exclude(&names(&["broken"])).is_err());
+}
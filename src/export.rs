@@ -0,0 +1,256 @@
+use serde_json::{json, Map, Value};
+
+use crate::cmdargs::Format;
+use crate::table::Column;
+use crate::walker::Counter;
+
+/// The version of the JSON schema, increase it when the existing fields are changed.
+/// Adding new fields doesn't change the version.
+pub const SCHEMA_VERSION: u64 = 1;
+
+/// The columns of a row in the machine-readable formats
+struct Row {
+    path: String,
+    n_files: u64,
+    n_dirs: u64,
+    size: u64,
+    // the figures are already included in another row, so they are left out of the total
+    overlapped: bool,
+    // the number of the rows collapsed into this one by `--limit`
+    collapsed: Option<usize>,
+}
+
+impl Row {
+    fn from_counter(cnt: &Counter) -> Self {
+        return Self {
+            path: cnt.dirpath.clone(),
+            n_files: cnt.n_files,
+            n_dirs: cnt.n_dirs,
+            size: cnt.size(),
+            overlapped: cnt.overlapped,
+            collapsed: Some(cnt.collapsed).filter(|&n| n > 0),
+        };
+    }
+
+    fn total(counters: &[Counter]) -> Self {
+        let (n_files, n_dirs, size) = Counter::sum(counters);
+        return Self {
+            path: String::from("Total"),
+            n_files,
+            n_dirs,
+            size,
+            overlapped: false,
+            collapsed: None,
+        };
+    }
+
+    // the raw value of a column, the derived columns are not exported
+    fn field(&self, col: Column) -> Option<Value> {
+        return match col {
+            Column::Path => Some(json!(self.path)),
+            Column::Files => Some(json!(self.n_files)),
+            Column::Dirs => Some(json!(self.n_dirs)),
+            Column::Size => Some(json!(self.size)),
+            _ => None,
+        };
+    }
+
+    // the flags of the row follow the columns, the collapsed count is only present in the collapsed row
+    fn to_json(&self, columns: &[Column]) -> Map<String, Value> {
+        let mut obj = Map::new();
+        for &col in columns {
+            if let Some(value) = self.field(col) {
+                obj.insert(col.name(), value);
+            }
+        }
+        obj.insert(String::from("overlapped"), json!(self.overlapped));
+        if let Some(n) = self.collapsed {
+            obj.insert(String::from("collapsed"), json!(n));
+        }
+        return obj;
+    }
+
+    // the flags of the row follow the columns, the collapsed count is empty unless it's the collapsed row
+    fn to_fields(&self, columns: &[Column]) -> Vec<String> {
+        let mut fields = Vec::from_iter(columns.iter().filter_map(|&col| match self.field(col)? {
+            Value::String(s) => Some(s),
+            value => Some(value.to_string()),
+        }));
+        fields.push(self.overlapped.to_string());
+        fields.push(self.collapsed.map(|n| n.to_string()).unwrap_or_default());
+        return fields;
+    }
+}
+
+fn header(columns: &[Column]) -> Vec<String> {
+    let mut header = Vec::from_iter(columns.iter().filter(|c| c.is_raw()).map(|c| c.name()));
+    header.extend([String::from("overlapped"), String::from("collapsed")]);
+    return header;
+}
+
+// quote the field if it contains the special chars of CSV
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        return format!("\"{}\"", field.replace('"', "\"\""));
+    }
+    return field.to_string();
+}
+
+// escape the special chars of TSV
+fn tsv_escape(field: &str) -> String {
+    return field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r");
+}
+
+fn to_json(counters: &[Counter], options: Value, columns: &[Column]) -> String {
+    let rows = Vec::from_iter(
+        counters
+            .iter()
+            .map(|c| Value::Object(Row::from_counter(c).to_json(columns))),
+    );
+    let total = Row::total(counters).to_json(columns);
+    let doc = json!({
+        "schema_version": SCHEMA_VERSION,
+        "options": options,
+        "rows": rows,
+        "total": total,
+    });
+    return serde_json::to_string_pretty(&doc).expect("serialize json err");
+}
+
+fn to_ndjson(counters: &[Counter], options: Value, columns: &[Column]) -> String {
+    let mut lines = vec![json!({
+        "type": "header",
+        "schema_version": SCHEMA_VERSION,
+        "options": options,
+    })
+    .to_string()];
+
+    let rows = counters.iter().map(|c| ("row", Row::from_counter(c)));
+    for (kind, row) in rows.chain([("total", Row::total(counters))]) {
+        let mut obj = Map::new();
+        obj.insert(String::from("type"), json!(kind));
+        obj.extend(row.to_json(columns));
+        lines.push(Value::Object(obj).to_string());
+    }
+    return lines.join("\n");
+}
+
+fn to_separated(counters: &[Counter], sep: &str, columns: &[Column]) -> String {
+    let escape = if sep == "," { csv_escape } else { tsv_escape };
+    let join = |fields: Vec<String>| Vec::from_iter(fields.iter().map(|f| escape(f))).join(sep);
+
+    let mut lines = vec![join(header(columns))];
+    for cnt in counters {
+        lines.push(join(Row::from_counter(cnt).to_fields(columns)));
+    }
+    lines.push(join(Row::total(counters).to_fields(columns)));
+    return lines.join("\n");
+}
+
+/// Render the counters in the machine-readable format.
+/// The numbers are raw integers, and the size is in bytes.
+/// Only the raw columns are exported, the percentages and bars can be derived from them.
+/// Each row is flagged whether it's overlapped by another row, and the row collapsed by `--limit` is marked.
+pub fn render(
+    counters: &[Counter],
+    format: Format,
+    options: Value,
+    columns: &[Column],
+) -> String {
+    return match format {
+        Format::Json => to_json(counters, options, columns),
+        Format::Ndjson => to_ndjson(counters, options, columns),
+        Format::Csv => to_separated(counters, ",", columns),
+        Format::Tsv => to_separated(counters, "\t", columns),
+        Format::Table | Format::Markdown | Format::Html => {
+            unreachable!("the tables are rendered by the table module")
+        }
+    };
+}
+
+#[cfg(test)]
+fn test_counters() -> Vec<Counter> {
+    use crate::testutil::counter;
+
+    return vec![counter("a,b", 3, 1), counter("tab\there", 2, 0)];
+}
+
+#[test]
+fn test_json() {
+    use Column::*;
+
+    let output = render(&test_counters(), Format::Json, json!({"all": true}), &[Path, Files, Dirs]);
+    let doc: Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(doc["schema_version"], SCHEMA_VERSION);
+    assert_eq!(doc["options"]["all"], true);
+    assert_eq!(doc["rows"][0], json!({"path": "a,b/", "files": 3, "dirs": 1, "overlapped": false}));
+    assert_eq!(doc["total"], json!({"path": "Total", "files": 5, "dirs": 1, "overlapped": false}));
+}
+
+#[test]
+fn test_ndjson() {
+    use Column::*;
+
+    let output = render(&test_counters(), Format::Ndjson, json!({}), &[Path, Files, Size, Bar]);
+    let lines = Vec::from_iter(output.lines());
+    assert_eq!(lines.len(), 4);
+    assert_eq!(
+        serde_json::from_str::<Value>(lines[0]).unwrap(),
+        json!({"type": "header", "schema_version": SCHEMA_VERSION, "options": {}})
+    );
+    assert_eq!(
+        serde_json::from_str::<Value>(lines[3]).unwrap(),
+        json!({"type": "total", "path": "Total", "files": 5, "size": 0, "overlapped": false})
+    );
+}
+
+#[test]
+fn test_csv_tsv() {
+    use Column::*;
+
+    assert_eq!(
+        render(&test_counters(), Format::Csv, json!({}), &[Path, Files, Dirs]),
+        "path,files,dirs,overlapped,collapsed\n\"a,b/\",3,1,false,\ntab\there/,2,0,false,\nTotal,5,1,false,"
+    );
+    assert_eq!(
+        render(&test_counters(), Format::Tsv, json!({}), &[Path, Files]),
+        "path\tfiles\toverlapped\tcollapsed\na,b/\t3\tfalse\t\ntab\\there/\t2\tfalse\t\nTotal\t5\tfalse\t"
+    );
+}
+
+#[test]
+fn test_column_order() {
+    use Column::*;
+
+    assert_eq!(
+        render(&test_counters(), Format::Csv, json!({}), &[Dirs, PctFiles, Path]),
+        "dirs,path,overlapped,collapsed\n1,\"a,b/\",false,\n0,tab\there/,false,\n1,Total,false,"
+    );
+}
+
+#[test]
+fn test_row_flags() {
+    use crate::testutil::counter;
+    use Column::*;
+
+    // the nested root is overlapped, and the collapsed row is marked
+    let mut nested = counter("a/b", 2, 0);
+    nested.overlapped = true;
+    let counters = Counter::collapse(vec![counter("a", 3, 1), nested, counter("c", 1, 0)], Some(2));
+    let output = render(&counters, Format::Json, json!({}), &[Path, Files]);
+    let doc: Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(doc["rows"][1], json!({"path": "a/b/", "files": 2, "overlapped": true}));
+    assert_eq!(
+        doc["rows"][2],
+        json!({"path": "(1 others)", "files": 1, "overlapped": false, "collapsed": 1})
+    );
+    assert_eq!(doc["total"]["files"], 4);
+    assert_eq!(
+        render(&counters, Format::Csv, json!({}), &[Path, Files]),
+        "path,files,overlapped,collapsed\na/,3,false,\na/b/,2,true,\n(1 others),1,false,1\nTotal,4,false,"
+    );
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+
+/// The colours of entries parsed from `LS_COLORS`, such as "di=01;34:ln=01;36:*.tar=01;31"
+#[derive(Debug, Clone, Default)]
+pub struct LsColors {
+    // the codes of file types, such as "di", "ln", "fi" and "ex"
+    types: HashMap<String, String>,
+    // the codes of file name suffixes, such as ".tar"
+    suffixes: Vec<(String, String)>,
+}
+
+impl LsColors {
+    pub fn parse(spec: &str) -> Self {
+        let mut colors = Self::default();
+        for item in spec.split(':') {
+            let Some((key, codes)) = item.split_once('=') else {
+                continue;
+            };
+            if let Some(suffix) = key.strip_prefix('*') {
+                colors.suffixes.push((suffix.to_lowercase(), codes.to_string()));
+            } else {
+                colors.types.insert(key.to_string(), codes.to_string());
+            }
+        }
+        // the longer suffixes take precedence
+        colors.suffixes.sort_by_key(|(suffix, _)| std::cmp::Reverse(suffix.len()));
+        return colors;
+    }
+
+    /// Read the colours from the `LS_COLORS` environment variable
+    pub fn from_env() -> Option<Self> {
+        return env::var("LS_COLORS").ok().map(|spec| Self::parse(&spec));
+    }
+
+    /// Find the codes for an entry.
+    /// `kind` is one of "file", "dir" and "symlink", and `mode` is only needed by the files.
+    pub fn codes(&self, path: &Path, kind: &str, mode: impl FnOnce() -> Option<u32>) -> Option<&str> {
+        let key = match kind {
+            "dir" => "di",
+            "symlink" => "ln",
+            _ => {
+                if self.types.contains_key("ex") && mode().is_some_and(|m| m & 0o111 != 0) {
+                    "ex"
+                } else {
+                    let name = path.file_name()?.to_string_lossy().to_lowercase();
+                    let matched = self.suffixes.iter().find(|(sfx, _)| name.ends_with(sfx));
+                    if let Some((_, codes)) = matched {
+                        return Some(codes);
+                    }
+                    "fi"
+                }
+            }
+        };
+        return self.types.get(key).map(|codes| codes.as_str());
+    }
+}
+
+#[test]
+fn test_ls_colors() {
+    let colors = LsColors::parse("rs=0:di=01;34:ln=01;36:ex=01;32:*.tar=01;31:*.tar.gz=01;35");
+    let codes = |path: &str, kind: &str, mode: u32| colors.codes(Path::new(path), kind, || Some(mode));
+
+    assert_eq!(codes("src", "dir", 0o755), Some("01;34"));
+    assert_eq!(codes("link", "symlink", 0o777), Some("01;36"));
+    assert_eq!(codes("run.sh", "file", 0o755), Some("01;32"));
+    assert_eq!(codes("a.TAR", "file", 0o644), Some("01;31"));
+    assert_eq!(codes("a.tar.gz", "file", 0o644), Some("01;35"));
+    assert_eq!(codes("a.txt", "file", 0o644), None);
+}
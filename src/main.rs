@@ -1,61 +1,180 @@
+#![allow(clippy::needless_return)]
+
+mod cache;
 mod cmdargs;
+mod config;
+mod export;
+mod lscolors;
+mod order;
 mod output;
+mod snapshot;
+mod table;
+mod template;
+#[cfg(test)]
+mod testutil;
+mod tree;
 mod walker;
+#[cfg(target_os = "linux")]
+mod watch;
+
+use std::path::PathBuf;
+use std::process::exit;
+use std::sync::Arc;
 
-use clap::Parser;
-use cmdargs::{CmdArgParser, OrderBy};
+use cache::Cache;
+use cmdargs::{CmdArgParser, Format};
+use config::Config;
+use lscolors::LsColors;
 use walker::Counter;
 
 fn main() {
-    // parse cmd-line args and get directories
-    let args = CmdArgParser::parse();
-    let with_size =
-        args.with_size || args.order_by == Some(OrderBy::S) || args.order_by == Some(OrderBy::Size);
-    let with_dir =
-        args.with_dir || args.order_by == Some(OrderBy::D) || args.order_by == Some(OrderBy::Dir);
+    // parse cmd-line args along with the defaults in the config file
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            let path = Config::path().unwrap_or_default();
+            output::print_err(&err, &path.display());
+            exit(1);
+        }
+    };
+    let args = CmdArgParser::parse_with_defaults(&config);
+    let ls_colors = match args.ls_colors {
+        true => LsColors::from_env(),
+        false => None,
+    };
+    output::Context::detect(args.color)
+        .with_theme(args.get_theme(&config))
+        .with_ls_colors(ls_colors)
+        .with_size_format(args.get_size_format())
+        .with_digit_sep(args.get_digit_sep())
+        .init();
+    if args.generate() {
+        return;
+    }
+    if let [old, new] = args.diff.as_slice() {
+        snapshot::output(&cmdargs::load_snapshot(old), &cmdargs::load_snapshot(new));
+        return;
+    }
+    let with_size = args.need_size();
+    let columns = args.get_columns();
 
     // walk all files
     let directories = args.get_directories();
-    let filter = args.get_regex();
-    let mut counters = Vec::<Counter>::new();
-    if args.non_recursive {
-        for dirpath in directories {
-            if let Ok((_, counter)) = walker::walk(
-                &dirpath,
-                args.all_files,
-                with_size,
-                filter.clone(),
-                args.verbose,
-            ) {
-                counters.push(counter);
-            };
-        }
-    } else {
-        counters = walker::parallel_walk(
-            directories,
-            args.all_files,
-            with_size,
-            filter,
-            args.verbose,
-            args.get_threads_num(),
-        );
-    }
-
-    match args.order_by {
-        Some(OrderBy::Name) | Some(OrderBy::N) => {
-            counters.sort_by(|c1, c2| c1.dirpath.cmp(&c2.dirpath));
+    let opts = walker::Options {
+        with_hidden: args.all_files,
+        with_size,
+        apparent_size: args.apparent_size || args.bytes,
+        filter: args.get_regex(),
+        dir_filter: args.get_dir_regex(),
+        exclude: args.get_exclude(&config),
+        verbose: args.verbose,
+        template: args.get_template(),
+        list_only: args.list,
+        recursive: !args.non_recursive,
+        exclude_nested: args.exclude_nested,
+        keep_dirs: args.tree,
+        cache: None,
+    };
+    let n_thread = match args.non_recursive {
+        true => 1,
+        false => args.get_threads_num(),
+    };
+    if args.watch {
+        // the counters are redrawn the same way as they are output once
+        let redraw = |mut counters: Vec<Counter>| {
+            order::sort(&mut counters, &args.order_by, args.reverse);
+            let counters = Counter::collapse(counters, args.get_limit());
+            return render(&counters, &args, &columns);
+        };
+        watch_roots(Vec::from_iter(directories), opts, redraw);
+    }
+
+    // the sizes and the listed entries can't be reused from the cache
+    let use_cache = (args.cache || args.rebuild_cache) && !args.no_cache;
+    let uncacheable = with_size || args.verbose || opts.template.is_some();
+    if use_cache && uncacheable {
+        let warning = output::ctx().warn(&"warning");
+        eprintln!("{}: the cache is not used with the size, -v, --printf or --list", warning);
+    }
+    let cache = match (use_cache && !uncacheable, Cache::default_path()) {
+        (true, Some(path)) => {
+            let fingerprint = cache::fingerprint(&opts);
+            Some(Arc::new(Cache::load(&path, fingerprint, args.rebuild_cache)))
         }
-        Some(OrderBy::File) | Some(OrderBy::F) => {
-            counters.sort_by(|c1, c2| c2.n_files.cmp(&c1.n_files));
+        _ => None,
+    };
+    let opts = walker::Options {
+        cache: cache.clone(),
+        ..opts
+    };
+    // the roots are kept for the cache as they are read
+    let mut roots = vec![];
+    let directories = directories.inspect(|path| roots.push(path.clone()));
+    let (mut counters, visits, roots_valid) = walker::parallel_walk(directories, opts, n_thread);
+    let dir_counters = Vec::from_iter(visits.into_iter().map(|v| v.cnt));
+    if let Some(cache) = cache {
+        if let Err(err) = cache.save(&roots, !args.non_recursive) {
+            output::print_err(&err, &"cache");
         }
-        Some(OrderBy::Dir) | Some(OrderBy::D) => {
-            counters.sort_by(|c1, c2| c2.n_dirs.cmp(&c1.n_dirs));
+    }
+
+    // the snapshot is made before the counters are sorted and collapsed
+    let snapshot = snapshot::Snapshot::new(&counters, &dir_counters, with_size);
+    if let Some(path) = &args.save {
+        if let Err(err) = snapshot.save(path) {
+            output::print_err(&err, &path.display());
+            exit(1);
         }
-        Some(OrderBy::Size) | Some(OrderBy::S) => {
-            counters.sort_by(|c1, c2| c2.size().cmp(&c1.size()));
+    }
+
+    order::sort(&mut counters, &args.order_by, args.reverse);
+    let counters = Counter::collapse(counters, args.get_limit());
+
+    // output the changes since the snapshot, or the counters unless the files have been listed
+    if let Some(path) = &args.compare {
+        snapshot::output(&cmdargs::load_snapshot(path), &snapshot);
+    } else if !args.list {
+        match args.format {
+            Format::Table if args.tree => {
+                let tree_opts = tree::TreeOptions {
+                    with_size,
+                    with_dirs: columns.contains(&table::Column::Dirs),
+                    depth: args.depth,
+                    min_percent: args.min_percent,
+                };
+                tree::output(&counters, &dir_counters, &tree_opts);
+            }
+            Format::Table => Counter::output(&counters, &columns),
+            _ => println!("{}", render(&counters, &args, &columns)),
         }
-        None => {}
     }
 
-    Counter::output(&counters, with_dir, with_size);
+    // some roots are invalid, they have been reported before
+    if !roots_valid {
+        exit(1);
+    }
+}
+
+// render the counters in the format of `--format`, the tree is output by itself
+fn render(counters: &[Counter], args: &CmdArgParser, columns: &[table::Column]) -> String {
+    match args.format {
+        Format::Table => return table::render(counters, columns),
+        Format::Markdown => return table::to_markdown(counters, columns),
+        Format::Html => return table::to_html(counters, columns),
+        format => return export::render(counters, format, args.options_json(), columns),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn watch_roots(roots: Vec<PathBuf>, opts: walker::Options, render: impl Fn(Vec<Counter>) -> String) -> ! {
+    if let Err(err) = watch::run(roots, opts, render) {
+        output::print_err(&err, &"watch");
+    }
+    exit(1);
+}
+
+#[cfg(not(target_os = "linux"))]
+fn watch_roots(_: Vec<PathBuf>, _: walker::Options, _: impl Fn(Vec<Counter>) -> String) -> ! {
+    eprintln!("{}: --watch is only supported on Linux", output::ctx().error(&"unsupported"));
+    exit(1);
 }
@@ -0,0 +1,113 @@
+use std::cmp::Ordering;
+
+use crate::table::{Column, Totals};
+use crate::walker::Counter;
+
+// split the string into the chunks of digits and non-digits
+fn chunks(s: &str) -> Vec<&str> {
+    let mut chunks = vec![];
+    let mut start = 0;
+    let mut prev_digit = None;
+    for (idx, chr) in s.char_indices() {
+        let is_digit = chr.is_ascii_digit();
+        if prev_digit.is_some_and(|d| d != is_digit) {
+            chunks.push(&s[start..idx]);
+            start = idx;
+        }
+        prev_digit = Some(is_digit);
+    }
+    if start < s.len() {
+        chunks.push(&s[start..]);
+    }
+    return chunks;
+}
+
+/// Compare two strings in natural order, the numbers in them are compared by value,
+/// so "run2" comes before "run10".
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    for (ca, cb) in chunks(a).iter().zip(chunks(b).iter()) {
+        let is_num = ca.as_bytes()[0].is_ascii_digit() && cb.as_bytes()[0].is_ascii_digit();
+        let ord = if is_num {
+            let na = ca.trim_start_matches('0');
+            let nb = cb.trim_start_matches('0');
+            na.len().cmp(&nb.len()).then_with(|| na.cmp(nb))
+        } else {
+            ca.cmp(cb)
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    // "a" < "a1", and "01" < "1" to keep the order total
+    return chunks(a).len().cmp(&chunks(b).len()).then_with(|| a.cmp(b));
+}
+
+/// Compare two counters by the column.
+/// The paths are in natural order, and the other columns are in descending order.
+pub fn compare(c1: &Counter, c2: &Counter, key: Column, total: Totals) -> Ordering {
+    return match (key.value(c1, total), key.value(c2, total)) {
+        (Some(v1), Some(v2)) => v2.partial_cmp(&v1).unwrap_or(Ordering::Equal),
+        _ => natural_cmp(&c1.dirpath, &c2.dirpath),
+    };
+}
+
+/// Sort the counters by the columns in turn, the ties keep their original order.
+pub fn sort(counters: &mut [Counter], keys: &[Column], reverse: bool) {
+    if keys.is_empty() {
+        if reverse {
+            counters.reverse();
+        }
+        return;
+    }
+
+    let total = Counter::sum(counters);
+    counters.sort_by(|c1, c2| {
+        let ord = keys
+            .iter()
+            .fold(Ordering::Equal, |ord, &key| ord.then_with(|| compare(c1, c2, key, total)));
+        if reverse {
+            ord.reverse()
+        } else {
+            ord
+        }
+    });
+}
+
+#[test]
+fn test_natural_cmp() {
+    let mut names = vec!["run10", "run2", "run1", "Run3", "run02", "run", "a10b2", "a10b10"];
+    names.sort_by(|a, b| natural_cmp(a, b));
+    assert_eq!(
+        names,
+        vec!["Run3", "a10b2", "a10b10", "run", "run1", "run02", "run2", "run10"]
+    );
+    assert_eq!(natural_cmp("v1.2.10", "v1.2.9"), Ordering::Greater);
+    assert_eq!(natural_cmp("same", "same"), Ordering::Equal);
+}
+
+#[test]
+fn test_sort() {
+    use crate::testutil::counter as make;
+
+    let names = |counters: &[Counter]| Vec::from_iter(counters.iter().map(|c| c.dirpath.clone()));
+    let mut counters = vec![
+        make("run10", 1, 5),
+        make("run2", 1, 5),
+        make("run1", 3, 2),
+        make("run3", 1, 7),
+    ];
+
+    sort(&mut counters, &[Column::Files, Column::Path], false);
+    assert_eq!(names(&counters), vec!["run1/", "run2/", "run3/", "run10/"]);
+
+    sort(&mut counters, &[Column::Dirs], true);
+    assert_eq!(names(&counters), vec!["run1/", "run2/", "run10/", "run3/"]);
+
+    // the ties keep their original order
+    sort(&mut counters, &[Column::Files], false);
+    assert_eq!(names(&counters), vec!["run1/", "run2/", "run10/", "run3/"]);
+
+    // the share columns are sorted by their values too
+    sort(&mut counters, &[Column::PctFiles, Column::Path], true);
+    assert_eq!(names(&counters), vec!["run10/", "run3/", "run2/", "run1/"]);
+}
@@ -1,7 +1,18 @@
+use std::env;
 use std::error::Error;
 use std::fmt::Display;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::cmdargs::ColorMode;
+use crate::lscolors::LsColors;
 
 #[allow(unused)]
+#[derive(Clone, Copy)]
 pub enum Effect {
     Default = 0,
     Bold = 1,
@@ -12,7 +23,23 @@ pub enum Effect {
     Hidden = 8,
 }
 
+impl Effect {
+    fn from_name(name: &str) -> Option<Self> {
+        return match name {
+            "default" => Some(Self::Default),
+            "bold" => Some(Self::Bold),
+            "dark" => Some(Self::Dark),
+            "inverse" => Some(Self::Inverse),
+            "underline" => Some(Self::Underline),
+            "blink" => Some(Self::Blink),
+            "hidden" => Some(Self::Hidden),
+            _ => None,
+        };
+    }
+}
+
 #[allow(unused)]
+#[derive(Clone, Copy)]
 pub enum Color {
     Black = 30,
     Red = 31,
@@ -33,44 +60,412 @@ pub enum Color {
     Default = 99,
 }
 
+impl Color {
+    fn from_name(name: &str) -> Option<Self> {
+        return match name {
+            "black" => Some(Self::Black),
+            "red" => Some(Self::Red),
+            "green" => Some(Self::Green),
+            "yellow" => Some(Self::Yellow),
+            "blue" => Some(Self::Blue),
+            "magenta" => Some(Self::Magenta),
+            "cyan" => Some(Self::Cyan),
+            "white" => Some(Self::White),
+            "grey" | "gray" => Some(Self::Grey),
+            "bright_red" => Some(Self::BrightRed),
+            "bright_green" => Some(Self::BrightGreen),
+            "bright_yellow" => Some(Self::BrightYellow),
+            "bright_blue" => Some(Self::BrightBlue),
+            "bright_magenta" => Some(Self::BrightMagenta),
+            "bright_cyan" => Some(Self::BrightCyan),
+            "bright_white" => Some(Self::BrightWhite),
+            "default" => Some(Self::Default),
+            _ => None,
+        };
+    }
+}
+
 #[allow(unused)]
 pub fn color_me(arg: &dyn Display, color: Color, effect: Effect) -> String {
     return format!("\x1b[{};{}m{}\x1b[0m", effect as u8, color as u8, arg);
 }
 
-#[allow(unused)]
-pub fn title(arg: &dyn Display) -> String {
-    return color_me(arg, Color::BrightGreen, Effect::Underline);
+/// An ANSI style made of the SGR codes, such as "4;92"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Style {
+    codes: String,
 }
 
-#[allow(unused)]
-pub fn info(arg: &dyn Display) -> String {
-    return color_me(arg, Color::BrightCyan, Effect::Default);
+impl Style {
+    pub fn new(color: Color, effect: Effect) -> Self {
+        return Self {
+            codes: format!("{};{}", effect as u8, color as u8),
+        };
+    }
+
+    /// Create a style from the raw SGR codes, such as the ones in `LS_COLORS`
+    pub fn from_codes(codes: &str) -> Self {
+        return Self {
+            codes: codes.to_string(),
+        };
+    }
+
+    /// Parse a style from the space-separated words, each word is one of
+    /// a color name ("bright_green"), an effect name ("underline"),
+    /// a 256-color code ("color(208)") and a truecolor code ("#ff8700").
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut codes = vec![];
+        for word in spec.split_whitespace() {
+            let word = word.to_lowercase();
+            if let Some(effect) = Effect::from_name(&word) {
+                codes.push((effect as u8).to_string());
+            } else if let Some(color) = Color::from_name(&word) {
+                codes.push((color as u8).to_string());
+            } else if let Some(n) = word.strip_prefix("color(").and_then(|w| w.strip_suffix(')')) {
+                let n: u8 = n.parse().map_err(|_| format!("invalid 256-color code '{}'", word))?;
+                codes.push(format!("38;5;{}", n));
+            } else if let Some(hex) = word.strip_prefix('#').filter(|h| h.len() == 6) {
+                let rgb = u32::from_str_radix(hex, 16)
+                    .map_err(|_| format!("invalid truecolor code '{}'", word))?;
+                codes.push(format!("38;2;{};{};{}", rgb >> 16, (rgb >> 8) & 0xff, rgb & 0xff));
+            } else {
+                return Err(format!("unknown color or effect '{}'", word));
+            }
+        }
+        return Ok(Self {
+            codes: codes.join(";"),
+        });
+    }
+
+    pub fn paint(&self, arg: &dyn Display) -> String {
+        return format!("\x1b[{}m{}\x1b[0m", self.codes, arg);
+    }
 }
 
-#[allow(unused)]
-pub fn note(arg: &dyn Display) -> String {
-    return color_me(arg, Color::Magenta, Effect::Default);
+/// The styles of each role in the output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    pub title: Style,
+    pub total: Style,
+    pub file: Style,
+    pub dir: Style,
+    pub symlink: Style,
+    pub error: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        return Self {
+            title: Style::new(Color::BrightGreen, Effect::Underline),
+            total: Style::new(Color::Default, Effect::Bold),
+            file: Style::new(Color::BrightCyan, Effect::Default),
+            dir: Style::new(Color::Yellow, Effect::Bold),
+            symlink: Style::new(Color::Magenta, Effect::Default),
+            error: Style::new(Color::Red, Effect::Bold),
+        };
+    }
 }
 
-#[allow(unused)]
-pub fn warn(arg: &dyn Display) -> String {
-    return color_me(arg, Color::Yellow, Effect::Bold);
+impl Theme {
+    pub fn builtin(name: &str) -> Option<Self> {
+        return match name {
+            "default" => Some(Self::default()),
+            // the darker colours for the light terminals
+            "light" => Some(Self {
+                title: Style::new(Color::Blue, Effect::Underline),
+                total: Style::new(Color::Default, Effect::Bold),
+                file: Style::new(Color::Cyan, Effect::Default),
+                dir: Style::new(Color::Magenta, Effect::Bold),
+                symlink: Style::new(Color::Blue, Effect::Default),
+                error: Style::new(Color::Red, Effect::Bold),
+            }),
+            _ => None,
+        };
+    }
 }
 
-#[allow(unused)]
-pub fn error(arg: &dyn Display) -> String {
-    return color_me(arg, Color::Red, Effect::Bold);
+/// How to render the sizes, it follows the semantics of `du`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizeFormat {
+    // 1024 or 1000 for the human-readable sizes
+    base: f64,
+    // scale the sizes to the block size and round them up, the suffix is appended
+    block: Option<(u64, String)>,
+    // the number of decimals of the human-readable sizes
+    precision: Option<usize>,
 }
 
-#[allow(unused)]
-pub fn strong(arg: &dyn Display) -> String {
-    return color_me(arg, Color::Default, Effect::Bold);
+impl Default for SizeFormat {
+    fn default() -> Self {
+        return Self::human(false, None);
+    }
+}
+
+impl SizeFormat {
+    const SZ_UNIT: [&'static str; 7] = ["B", "K", "M", "G", "T", "P", "E"];
+    const SI_UNIT: [&'static str; 7] = ["B", "k", "M", "G", "T", "P", "E"];
+
+    /// The human-readable sizes, in powers of 1000 if `si` is true, otherwise in powers of 1024
+    pub fn human(si: bool, precision: Option<usize>) -> Self {
+        return Self {
+            base: if si { 1000.0 } else { 1024.0 },
+            block: None,
+            precision,
+        };
+    }
+
+    /// The sizes in units of the block size, such as "K" and "MB"
+    pub fn block(block_size: u64, suffix: &str) -> Self {
+        return Self {
+            base: 1024.0,
+            block: Some((block_size, suffix.to_string())),
+            precision: None,
+        };
+    }
+
+    /// The exact sizes in bytes
+    pub fn bytes() -> Self {
+        return Self::block(1, "");
+    }
+
+    /// Parse the block size like `du --block-size`: K, M, G, T (powers of 1024)
+    /// or KB, MB, GB, TB (powers of 1000).
+    pub fn parse_block_size(arg: &str) -> Result<Self, String> {
+        let units = ["K", "M", "G", "T"];
+        let upper = arg.to_uppercase();
+        let (name, base) = match upper.strip_suffix("B") {
+            Some(name) if !name.is_empty() => (name, 1000_u64),
+            _ => (upper.as_str(), 1024_u64),
+        };
+        match units.iter().position(|u| *u == name) {
+            Some(idx) => return Ok(Self::block(base.pow(idx as u32 + 1), &upper)),
+            None => return Err(format!("invalid block size '{}', use K, M, G, T, KB, MB, GB or TB", arg)),
+        }
+    }
+
+    pub fn format(&self, size: u64) -> String {
+        if let Some((block_size, suffix)) = &self.block {
+            return format!("{}{}", size.div_ceil(*block_size), suffix);
+        }
+
+        let units = if self.base == 1000.0 { Self::SI_UNIT } else { Self::SZ_UNIT };
+        let mut sz = size as f64;
+        let mut str_sz = String::new();
+        for (idx, unit) in units.iter().enumerate() {
+            if sz >= self.base && idx + 1 < units.len() {
+                sz /= self.base;
+            } else {
+                str_sz = match self.precision {
+                    _ if idx == 0 => format!("{:.0}{}", sz, unit),
+                    Some(precision) => format!("{:.*}{}", precision, sz, unit),
+                    None if sz.fract() < 0.05 => format!("{:.0}{}", sz, unit),
+                    None => format!("{:.1}{}", sz, unit),
+                };
+                break;
+            }
+        }
+        return str_sz;
+    }
+}
+
+/// The output context, all of the colour decisions are made here
+pub struct Context {
+    color: bool,
+    // whether to colour the errors on stderr
+    err_color: bool,
+    theme: Theme,
+    ls_colors: Option<LsColors>,
+    size_format: SizeFormat,
+    // the thousands separator of the counts
+    digit_sep: Option<char>,
+}
+
+static CONTEXT: OnceLock<Context> = OnceLock::new();
+
+impl Context {
+    pub fn new(color: bool) -> Self {
+        return Self {
+            color,
+            err_color: color,
+            theme: Theme::default(),
+            ls_colors: None,
+            size_format: SizeFormat::default(),
+            digit_sep: None,
+        };
+    }
+
+    /// Decide whether to colour the output by the mode, the environment variables and the TTY.
+    /// `NO_COLOR` disables the colours and `CLICOLOR_FORCE` enables them in the auto mode.
+    /// stdout and stderr are decided by their own TTYs, since either of them may be redirected.
+    pub fn detect(mode: ColorMode) -> Self {
+        // any non-empty `NO_COLOR` counts, while `CLICOLOR_FORCE=0` doesn't force the colours
+        let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        let force = env::var_os("CLICOLOR_FORCE").is_some_and(|v| !v.is_empty() && v != "0");
+        let decide = |is_tty: bool| match mode {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto if no_color => false,
+            ColorMode::Auto if force => true,
+            ColorMode::Auto => is_tty,
+        };
+        let mut ctx = Self::new(decide(io::stdout().is_terminal()));
+        ctx.err_color = decide(io::stderr().is_terminal());
+        return ctx;
+    }
+
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        return self;
+    }
+
+    /// Colour the paths of the found entries by `LS_COLORS`
+    pub fn with_ls_colors(mut self, ls_colors: Option<LsColors>) -> Self {
+        self.ls_colors = ls_colors;
+        return self;
+    }
+
+    pub fn with_size_format(mut self, size_format: SizeFormat) -> Self {
+        self.size_format = size_format;
+        return self;
+    }
+
+    /// Render a size in bytes
+    pub fn size(&self, size: u64) -> String {
+        return self.size_format.format(size);
+    }
+
+    /// Group the digits of the counts by the separator, such as "17,154,321"
+    pub fn with_digit_sep(mut self, digit_sep: Option<char>) -> Self {
+        self.digit_sep = digit_sep;
+        return self;
+    }
+
+    /// Render a count
+    pub fn count(&self, n: u64) -> String {
+        match self.digit_sep {
+            Some(sep) => return group_digits(n, sep),
+            None => return n.to_string(),
+        }
+    }
+
+    /// Set the global output context, it can only be set once.
+    pub fn init(self) {
+        let _ = CONTEXT.set(self);
+    }
+
+    pub fn paint(&self, arg: &dyn Display, style: &Style) -> String {
+        if self.color {
+            return style.paint(arg);
+        } else {
+            return arg.to_string();
+        }
+    }
+
+    pub fn title(&self, arg: &dyn Display) -> String {
+        return self.paint(arg, &self.theme.title);
+    }
+
+    /// the style of files
+    pub fn info(&self, arg: &dyn Display) -> String {
+        return self.paint(arg, &self.theme.file);
+    }
+
+    /// the style of symlinks
+    pub fn note(&self, arg: &dyn Display) -> String {
+        return self.paint(arg, &self.theme.symlink);
+    }
+
+    /// the style of dirs
+    pub fn warn(&self, arg: &dyn Display) -> String {
+        return self.paint(arg, &self.theme.dir);
+    }
+
+    /// the style of errors, they are written to stderr, so they follow the colour decision of stderr
+    pub fn error(&self, arg: &dyn Display) -> String {
+        if self.err_color {
+            return self.theme.error.paint(arg);
+        } else {
+            return arg.to_string();
+        }
+    }
+
+    /// the style of the total
+    pub fn strong(&self, arg: &dyn Display) -> String {
+        return self.paint(arg, &self.theme.total);
+    }
+
+    /// Colour the path of a found entry by `LS_COLORS`, if it's enabled.
+    /// `kind` is one of "file", "dir" and "symlink", and `mode` is only needed by the files.
+    pub fn entry_path(&self, path: &Path, kind: &str, mode: impl FnOnce() -> Option<u32>) -> String {
+        let path_str = path.to_string_lossy();
+        if let Some(ls_colors) = self.ls_colors.as_ref().filter(|_| self.color) {
+            if let Some(codes) = ls_colors.codes(path, kind, mode) {
+                return Style::from_codes(codes).paint(&path_str);
+            }
+        }
+        return path_str.to_string();
+    }
+}
+
+/// Insert the separator into every three digits, such as "17,154,321"
+pub fn group_digits(n: u64, sep: char) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::new();
+    for (idx, chr) in digits.chars().enumerate() {
+        if idx > 0 && (digits.len() - idx) % 3 == 0 {
+            grouped.push(sep);
+        }
+        grouped.push(chr);
+    }
+    return grouped;
+}
+
+/// Get the thousands separator of the locale from `LC_ALL` or `LC_NUMERIC`.
+/// None for the "C" and "POSIX" locales and the unknown languages.
+pub fn locale_digit_sep() -> Option<char> {
+    let locale = ["LC_ALL", "LC_NUMERIC"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|v| !v.is_empty())?;
+    let lang = locale.split(['_', '.', '@']).next()?;
+    return match lang {
+        "en" | "ja" | "zh" | "ko" | "he" | "th" | "hi" => Some(','),
+        "de" | "it" | "es" | "nl" | "pt" | "da" | "id" | "tr" | "el" | "ro" => Some('.'),
+        "fr" | "ru" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "no" | "uk" | "hu" => Some(' '),
+        _ => None,
+    };
+}
+
+/// Get the global output context, it's detected automatically if it hasn't been set.
+pub fn ctx() -> &'static Context {
+    return CONTEXT.get_or_init(|| Context::detect(ColorMode::Auto));
 }
 
 pub fn print_err(err: &dyn Error, msg: &dyn Display) {
     let head = err.to_string();
-    eprintln!("{}: {}", error(&head), msg)
+    eprintln!("{}: {}", ctx().error(&head), msg)
+}
+
+/// The width of the terminal from the TTY of stdout or `COLUMNS`, 80 by default
+pub fn terminal_width() -> usize {
+    // SAFETY: `winsize` is a plain C struct, and it's only read when ioctl succeeds.
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0 && size.ws_col > 0 {
+        return size.ws_col as usize;
+    }
+    return env::var("COLUMNS")
+        .ok()
+        .and_then(|cols| cols.parse().ok())
+        .unwrap_or(80);
+}
+
+/// The percentage of the part in the whole, 0 if the whole is 0
+pub fn percent(part: u64, whole: u64) -> f64 {
+    return if whole == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / whole as f64
+    };
 }
 
 pub fn fill_char(chr: char, width: usize) -> String {
@@ -82,10 +477,13 @@ pub fn spaces(width: usize) -> String {
     return fill_char(' ', width);
 }
 
-pub fn display_width(s: &String) -> usize {
+/// The number of terminal columns taken by the string.
+/// Each grapheme cluster takes at most 2 columns, so the combining characters and
+/// the emoji sequences are counted once, and the East Asian ambiguous characters take 1 column.
+pub fn display_width(s: &str) -> usize {
     return s
-        .chars()
-        .map(|c| if c as u32 > 0x2e80 { 2_usize } else { 1_usize })
+        .graphemes(true)
+        .map(|g| UnicodeWidthStr::width(g).min(2))
         .sum::<usize>();
 }
 
@@ -132,14 +530,15 @@ pub fn right_justify(s: &dyn ToString, width: usize) -> String {
 #[test]
 fn test_color() {
     let s = "Hello World";
+    let ctx = Context::new(true);
     println!(
         "yellow + underline: {}\n",
         color_me(&s, Color::Yellow, Effect::Underline)
     );
-    println!("title: {}", title(&s));
-    println!("info : {}", info(&s));
-    println!("warn : {}", warn(&s));
-    println!("error  : {}", error(&s));
+    println!("title: {}", ctx.title(&s));
+    println!("info : {}", ctx.info(&s));
+    println!("warn : {}", ctx.warn(&s));
+    println!("error  : {}", ctx.error(&s));
 }
 
 #[test]
@@ -150,8 +549,8 @@ fn test_align() {
     assert_eq!(left_justify(&s, 15), String::from("HelloWorld     "));
     assert_eq!(right_justify(&s, 15), String::from("     HelloWorld"));
 
-    let t = title(
-        &vec![
+    let t = Context::new(true).title(
+        &[
             left_justify(&"Name", 8),
             right_justify(&"Files", 5),
             right_justify(&"Dirs", 5),
@@ -182,12 +581,15 @@ fn test_non_ascii() {
     println!("s1 => |{}|", aligned_s1);
     println!("s2 => |{}|", aligned_s2);
     println!("s3 => |{}|", aligned_s3);
-    println!("s3 => |{}|", aligned_s4);
+    println!("s4 => |{}|", aligned_s4);
 
-    assert_eq!(aligned_s1.len(), 25);
-    assert_eq!(aligned_s2.len(), 27);
-    assert_eq!(aligned_s3.len(), 30);
-    assert_eq!(aligned_s4.len(), 34);
+    assert_eq!(display_width(&aligned_s1), 25);
+    assert_eq!(display_width(&aligned_s2), 25);
+    assert_eq!(display_width(&aligned_s3), 25);
+    assert_eq!(display_width(&aligned_s4), 25);
+    assert_eq!(aligned_s2, "        你好 Rust        ");
+    assert_eq!(aligned_s3, "Séamile: 🌊😀            ");
+    assert_eq!(aligned_s4, "EVA，人の作り出した物    ");
 }
 
 #[test]
@@ -204,13 +606,13 @@ fn test_color_me() {
 
 #[test]
 fn test_title() {
-    assert_eq!(title(&"Welcome"), "\x1b[4;92mWelcome\x1b[0m");
+    assert_eq!(Context::new(true).title(&"Welcome"), "\x1b[4;92mWelcome\x1b[0m");
 }
 
 #[test]
 fn test_info() {
     assert_eq!(
-        info(&"This is an information"),
+        Context::new(true).info(&"This is an information"),
         "\x1b[0;96mThis is an information\x1b[0m"
     );
 }
@@ -218,7 +620,7 @@ fn test_info() {
 #[test]
 fn test_note() {
     assert_eq!(
-        note(&"Note: this is important"),
+        Context::new(true).note(&"Note: this is important"),
         "\x1b[0;35mNote: this is important\x1b[0m"
     );
 }
@@ -226,7 +628,7 @@ fn test_note() {
 #[test]
 fn test_warn() {
     assert_eq!(
-        warn(&"Warning: something may go wrong"),
+        Context::new(true).warn(&"Warning: something may go wrong"),
         "\x1b[1;33mWarning: something may go wrong\x1b[0m"
     );
 }
@@ -234,14 +636,101 @@ fn test_warn() {
 #[test]
 fn test_error() {
     assert_eq!(
-        error(&"Error: something went wrong"),
+        Context::new(true).error(&"Error: something went wrong"),
         "\x1b[1;31mError: something went wrong\x1b[0m"
     );
 }
 
 #[test]
 fn test_strong() {
-    assert_eq!(strong(&"strong text"), "\x1b[1;99mstrong text\x1b[0m");
+    assert_eq!(Context::new(true).strong(&"strong text"), "\x1b[1;99mstrong text\x1b[0m");
+}
+
+#[test]
+fn test_no_color() {
+    let ctx = Context::new(false);
+    assert_eq!(ctx.title(&"Welcome"), "Welcome");
+    assert_eq!(ctx.paint(&"hello", &Style::new(Color::Red, Effect::Bold)), "hello");
+    assert_eq!(Context::detect(ColorMode::Never).title(&"Welcome"), "Welcome");
+    assert_eq!(Context::detect(ColorMode::Never).error(&"oops"), "oops");
+    assert_eq!(
+        Context::detect(ColorMode::Always).title(&"Welcome"),
+        "\x1b[4;92mWelcome\x1b[0m"
+    );
+    assert_eq!(Context::detect(ColorMode::Always).error(&"oops"), "\x1b[1;31moops\x1b[0m");
+
+    // the errors on stderr are decided apart from stdout
+    let mut ctx = Context::new(true);
+    ctx.err_color = false;
+    assert_eq!(ctx.error(&"oops"), "oops");
+}
+
+#[test]
+fn test_style() {
+    assert_eq!(
+        Style::parse("underline bright_green").unwrap(),
+        Style::new(Color::BrightGreen, Effect::Underline)
+    );
+    assert_eq!(
+        Style::parse("Bold color(208)").unwrap().paint(&"hi"),
+        "\x1b[1;38;5;208mhi\x1b[0m"
+    );
+    assert_eq!(
+        Style::parse("#ff8700").unwrap().paint(&"hi"),
+        "\x1b[38;2;255;135;0mhi\x1b[0m"
+    );
+    assert!(Style::parse("pink").is_err());
+    assert!(Style::parse("color(256)").is_err());
+    assert!(Style::parse("#ff87zz").is_err());
+}
+
+#[test]
+fn test_theme() {
+    let ctx = Context::new(true).with_theme(Theme::builtin("light").unwrap());
+    assert_eq!(ctx.title(&"Welcome"), "\x1b[4;34mWelcome\x1b[0m");
+    assert!(Theme::builtin("unknown").is_none());
+
+    let ctx = ctx.with_ls_colors(Some(LsColors::parse("di=01;34")));
+    assert_eq!(ctx.entry_path(Path::new("src"), "dir", || None), "\x1b[01;34msrc\x1b[0m");
+    assert_eq!(ctx.entry_path(Path::new("a.rs"), "file", || None), "a.rs");
+}
+
+#[test]
+fn test_size_format() {
+    let human = SizeFormat::default();
+    assert_eq!(human.format(0), "0B");
+    assert_eq!(human.format(1023), "1023B");
+    assert_eq!(human.format(1434), "1.4K");
+    assert_eq!(human.format(0xffffffffffffffff), "16E");
+
+    let si = SizeFormat::human(true, Some(2));
+    assert_eq!(si.format(999), "999B");
+    assert_eq!(si.format(1434), "1.43k");
+    assert_eq!(si.format(18_100_000_000), "18.10G");
+    assert_eq!(SizeFormat::human(false, Some(0)).format(1434), "1K");
+
+    assert_eq!(SizeFormat::bytes().format(1434), "1434");
+    let mb = SizeFormat::parse_block_size("M").unwrap();
+    assert_eq!(mb.format(1), "1M");
+    assert_eq!(mb.format(1048576), "1M");
+    assert_eq!(mb.format(1048577), "2M");
+    let gb = SizeFormat::parse_block_size("gb").unwrap();
+    assert_eq!(gb.format(2_000_000_000), "2GB");
+    assert!(SizeFormat::parse_block_size("B").is_err());
+    assert!(SizeFormat::parse_block_size("X").is_err());
+}
+
+#[test]
+fn test_group_digits() {
+    assert_eq!(group_digits(0, ','), "0");
+    assert_eq!(group_digits(999, ','), "999");
+    assert_eq!(group_digits(1000, ','), "1,000");
+    assert_eq!(group_digits(17154321, ','), "17,154,321");
+    assert_eq!(group_digits(17154321, ' '), "17 154 321");
+
+    let ctx = Context::new(false).with_digit_sep(Some('.'));
+    assert_eq!(ctx.count(123456), "123.456");
+    assert_eq!(Context::new(false).count(123456), "123456");
 }
 
 #[test]
@@ -258,9 +747,18 @@ fn test_spaces() {
 
 #[test]
 fn test_display_width() {
-    assert_eq!(display_width(&"hello".to_string()), 5);
-    assert_eq!(display_width(&"你好".to_string()), 4);
-    assert_eq!(display_width(&"abc你好".to_string()), 6);
+    assert_eq!(display_width("hello"), 5);
+    assert_eq!(display_width("你好"), 4);
+    assert_eq!(display_width("abc你好"), 7);
+    // combining accent
+    assert_eq!(display_width("e\u{301}te\u{301}"), 3);
+    // emoji below U+2E80, with the variation selector and the zero-width joiner
+    assert_eq!(display_width("☕"), 2);
+    assert_eq!(display_width("❤\u{fe0f}"), 2);
+    assert_eq!(display_width("👨\u{200d}👩\u{200d}👧"), 2);
+    // East Asian ambiguous
+    assert_eq!(display_width("±°"), 2);
+    assert_eq!(display_width("日本語のファイル.txt"), 20);
 }
 
 #[test]
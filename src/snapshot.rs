@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::output as op;
+use crate::walker::Counter;
+
+/// The version of the snapshot file, increase it when the existing fields are changed.
+pub const SNAPSHOT_VERSION: u64 = 1;
+
+/// The figures of a root or a directory in the snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    pub path: String,
+    pub files: u64,
+    pub dirs: u64,
+    pub size: u64,
+    /// the figures are already included in another root
+    #[serde(default)]
+    pub overlapped: bool,
+}
+
+impl Record {
+    fn from_counter(cnt: &Counter) -> Self {
+        return Self {
+            path: cnt.dirpath.clone(),
+            files: cnt.n_files,
+            dirs: cnt.n_dirs,
+            size: cnt.size(),
+            overlapped: cnt.overlapped,
+        };
+    }
+}
+
+/// The counters of a run, saved by `--save`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub schema_version: u64,
+    /// the seconds since the Unix epoch
+    pub timestamp: u64,
+    /// whether the size of files was counted
+    pub with_size: bool,
+    pub roots: Vec<Record>,
+    /// the direct counters of each traversed directory, only available in the tree view
+    #[serde(default)]
+    pub dirs: Vec<Record>,
+}
+
+impl Snapshot {
+    pub fn new(counters: &[Counter], dirs: &[Counter], with_size: bool) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        return Self {
+            schema_version: SNAPSHOT_VERSION,
+            timestamp,
+            with_size,
+            roots: Vec::from_iter(counters.iter().map(Record::from_counter)),
+            dirs: Vec::from_iter(dirs.iter().map(Record::from_counter)),
+        };
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        return Ok(serde_json::from_str(&content)?);
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        return fs::write(path, content + "\n");
+    }
+}
+
+/// Whether a path is in both snapshots, or only in one of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Kept,
+    Appeared,
+    Disappeared,
+}
+
+/// The change of a path between two snapshots
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delta {
+    pub path: String,
+    pub new: Record,
+    pub files: i64,
+    pub dirs: i64,
+    pub size: i64,
+    /// the growth in percent, None if the path is not in the old snapshot or it was empty
+    pub growth: Option<f64>,
+    pub status: Status,
+}
+
+fn delta(old: u64, new: u64) -> i64 {
+    return new as i64 - old as i64;
+}
+
+/// Compare the records by path, the deltas are sorted by the largest change first.
+/// The change is measured by size if `with_size`, otherwise by files.
+pub fn compare(old: &[Record], new: &[Record], with_size: bool) -> Vec<Delta> {
+    let olds = HashMap::<&str, &Record>::from_iter(old.iter().map(|r| (r.path.as_str(), r)));
+    let news = HashMap::<&str, &Record>::from_iter(new.iter().map(|r| (r.path.as_str(), r)));
+    let empty = |path: &str| Record {
+        path: path.to_string(),
+        files: 0,
+        dirs: 0,
+        size: 0,
+        overlapped: false,
+    };
+
+    let mut deltas = vec![];
+    let gone = old.iter().filter(|r| !news.contains_key(r.path.as_str()));
+    for rec in new.iter().chain(gone) {
+        let (old_rec, new_rec, status) = match (olds.get(rec.path.as_str()), news.get(rec.path.as_str())) {
+            (Some(&o), Some(&n)) => (o.clone(), n.clone(), Status::Kept),
+            (None, Some(&n)) => (empty(&rec.path), n.clone(), Status::Appeared),
+            (Some(&o), None) => (o.clone(), empty(&rec.path), Status::Disappeared),
+            (None, None) => unreachable!("the path is in one of the snapshots"),
+        };
+        let (old_metric, new_metric) = match with_size {
+            true => (old_rec.size, new_rec.size),
+            false => (old_rec.files, new_rec.files),
+        };
+        let growth = match status == Status::Appeared || old_metric == 0 {
+            true => None,
+            false => Some(delta(old_metric, new_metric) as f64 * 100.0 / old_metric as f64),
+        };
+        deltas.push(Delta {
+            path: rec.path.clone(),
+            files: delta(old_rec.files, new_rec.files),
+            dirs: delta(old_rec.dirs, new_rec.dirs),
+            size: delta(old_rec.size, new_rec.size),
+            new: Record {
+                overlapped: new_rec.overlapped || old_rec.overlapped,
+                ..new_rec
+            },
+            growth,
+            status,
+        });
+    }
+
+    let change = |d: &Delta| if with_size { d.size.unsigned_abs() } else { d.files.unsigned_abs() };
+    deltas.sort_by(|a, b| change(b).cmp(&change(a)).then_with(|| a.path.cmp(&b.path)));
+    return deltas;
+}
+
+// format a delta with its sign, such as "+12" and "-3"
+fn signed(n: i64, fmt: impl Fn(u64) -> String) -> String {
+    return match n {
+        0 => String::from("0"),
+        n if n > 0 => format!("+{}", fmt(n as u64)),
+        n => format!("-{}", fmt(n.unsigned_abs())),
+    };
+}
+
+/// Render the deltas as a table, the Total skips the overlapped rows
+fn render(deltas: &[Delta], with_size: bool) -> String {
+    let mut head = vec!["Path", "Files", "ΔFiles", "Dirs", "ΔDirs"];
+    if with_size {
+        head.extend(["Size", "ΔSize"]);
+    }
+    head.extend(["Growth", "Status"]);
+
+    let make_row = |path: &str, new: &Record, d: (i64, i64, i64), growth: Option<f64>, status: &str| {
+        let mut row = vec![
+            path.to_string(),
+            op::ctx().count(new.files),
+            signed(d.0, |n| op::ctx().count(n)),
+            op::ctx().count(new.dirs),
+            signed(d.1, |n| op::ctx().count(n)),
+        ];
+        if with_size {
+            row.push(op::ctx().size(new.size));
+            row.push(signed(d.2, |n| op::ctx().size(n)));
+        }
+        row.push(growth.map_or(String::from("-"), |g| format!("{:+.1}%", g)));
+        row.push(status.to_string());
+        return row;
+    };
+
+    let mut rows = vec![];
+    let mut total = Record {
+        path: String::from("Total"),
+        files: 0,
+        dirs: 0,
+        size: 0,
+        overlapped: false,
+    };
+    let mut total_delta = (0_i64, 0_i64, 0_i64);
+    for d in deltas {
+        let status = match d.status {
+            Status::Kept => "",
+            Status::Appeared => "appeared",
+            Status::Disappeared => "disappeared",
+        };
+        rows.push(make_row(&d.path, &d.new, (d.files, d.dirs, d.size), d.growth, status));
+        if !d.new.overlapped {
+            total.files += d.new.files;
+            total.dirs += d.new.dirs;
+            total.size += d.new.size;
+            total_delta = (total_delta.0 + d.files, total_delta.1 + d.dirs, total_delta.2 + d.size);
+        }
+    }
+    let old_metric = match with_size {
+        true => total.size as i64 - total_delta.2,
+        false => total.files as i64 - total_delta.0,
+    };
+    let total_growth = match old_metric {
+        0 => None,
+        old => Some(if with_size { total_delta.2 } else { total_delta.0 } as f64 * 100.0 / old as f64),
+    };
+    let total_row = make_row("Total", &total, total_delta, total_growth, "");
+
+    // calculate the max width of each column, only the path and the status are aligned to the left
+    let mut widths = Vec::from_iter(head.iter().map(|h| op::display_width(h)));
+    for row in rows.iter().chain([&total_row]) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(op::display_width(cell));
+        }
+    }
+    let last = head.len() - 1;
+    let join = |cells: &[String]| {
+        let mut fields = vec![];
+        for (idx, (cell, &width)) in cells.iter().zip(&widths).enumerate() {
+            if idx == 0 || idx == last {
+                fields.push(op::left_justify(cell, width));
+            } else {
+                fields.push(op::right_justify(cell, width));
+            }
+        }
+        return fields.join("  ").trim_end().to_string();
+    };
+
+    let head = Vec::from_iter(head.iter().map(|h| h.to_string()));
+    let mut lines = vec![op::ctx().title(&join(&head))];
+    for row in &rows {
+        lines.push(join(row));
+    }
+    let total_line = join(&total_row);
+    let line_width = widths.iter().sum::<usize>() + 2 * last;
+    lines.push(op::fill_char('─', line_width.max(op::display_width(&total_line))));
+    lines.push(op::ctx().strong(&total_line));
+    return lines.join("\n");
+}
+
+// convert the seconds since the Unix epoch to "YYYY-MM-DD HH:MM:SS UTC"
+fn format_utc(timestamp: u64) -> String {
+    let (days, secs) = (timestamp / 86400, timestamp % 86400);
+    // the algorithm of `civil_from_days` by Howard Hinnant
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    return format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    );
+}
+
+/// Output the differences between two snapshots.
+/// The directories are compared too if both snapshots have them.
+pub fn output(old: &Snapshot, new: &Snapshot) {
+    let with_size = old.with_size && new.with_size;
+    let mut sections = vec![format!(
+        "{} → {}",
+        format_utc(old.timestamp),
+        format_utc(new.timestamp)
+    )];
+    sections.push(render(&compare(&old.roots, &new.roots, with_size), with_size));
+    if !old.dirs.is_empty() && !new.dirs.is_empty() {
+        // the direct counters don't overlap with each other
+        let deltas = compare(&old.dirs, &new.dirs, with_size);
+        sections.push(render(&deltas, with_size));
+    }
+    println!("{}", sections.join("\n\n"));
+}
+
+#[cfg(test)]
+fn record(path: &str, files: u64, size: u64) -> Record {
+    return Record {
+        path: path.to_string(),
+        files,
+        dirs: 0,
+        size,
+        overlapped: false,
+    };
+}
+
+#[test]
+fn test_compare() {
+    let old = [record("a/", 10, 100), record("b/", 5, 50), record("c/", 1, 10)];
+    let new = [record("a/", 12, 90), record("b/", 5, 500), record("d/", 3, 30)];
+
+    let deltas = compare(&old, &new, true);
+    let summary = Vec::from_iter(deltas.iter().map(|d| (d.path.as_str(), d.size, d.status)));
+    assert_eq!(
+        summary,
+        [
+            ("b/", 450, Status::Kept),
+            ("d/", 30, Status::Appeared),
+            ("a/", -10, Status::Kept),
+            ("c/", -10, Status::Disappeared),
+        ]
+    );
+    assert_eq!(deltas[0].growth, Some(900.0));
+    assert_eq!(deltas[1].growth, None);
+    assert_eq!(deltas[3].growth, Some(-100.0));
+
+    let deltas = compare(&old, &new, false);
+    assert_eq!(deltas[0].path, "d/");
+    assert_eq!(deltas[0].files, 3);
+    assert_eq!(deltas[3].path, "b/");
+}
+
+#[test]
+fn test_render_deltas() {
+    let old = [record("a/", 10, 0), record("c/", 1, 0)];
+    let new = [record("a/", 12, 0)];
+    let output = render(&compare(&old, &new, false), false);
+    let lines = Vec::from_iter(output.lines());
+    assert_eq!(lines[0], "Path   Files  ΔFiles  Dirs  ΔDirs   Growth  Status");
+    assert_eq!(lines[1], "a/        12      +2     0      0   +20.0%");
+    assert_eq!(lines[2], "c/         0      -1     0      0  -100.0%  disappeared");
+    assert_eq!(lines[4], "Total     12      +1     0      0    +9.1%");
+}
+
+#[test]
+fn test_format_utc() {
+    assert_eq!(format_utc(0), "1970-01-01 00:00:00 UTC");
+    assert_eq!(format_utc(951782400), "2000-02-29 00:00:00 UTC");
+    assert_eq!(format_utc(1735689599), "2024-12-31 23:59:59 UTC");
+}
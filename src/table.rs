@@ -0,0 +1,340 @@
+use clap::ValueEnum;
+
+use crate::output as op;
+use crate::walker::Counter;
+
+/// The width of the bar column
+const BAR_WIDTH: usize = 20;
+
+/// The columns of the table
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Column {
+    /// the path of the directory
+    #[value(alias = "name", alias = "n")]
+    Path,
+    /// the number of files
+    #[value(alias = "file", alias = "f")]
+    Files,
+    /// the number of directories
+    #[value(alias = "dir", alias = "d")]
+    Dirs,
+    /// the total size of files
+    #[value(alias = "s")]
+    Size,
+    /// the percentage of files in the Total
+    PctFiles,
+    /// the percentage of size in the Total
+    PctSize,
+    /// the share of the Total as a bar, by size if the size is counted, otherwise by files
+    Bar,
+}
+
+/// The sums of files, dirs and size, the percentages are relative to them
+pub type Totals = (u64, u64, u64);
+
+impl Column {
+    pub fn title(self) -> &'static str {
+        return match self {
+            Self::Path => "Path",
+            Self::Files => "Files",
+            Self::Dirs => "Dirs",
+            Self::Size => "Size",
+            Self::PctFiles => "%Files",
+            Self::PctSize => "%Size",
+            Self::Bar => "Share",
+        };
+    }
+
+    /// The name used by `--columns` and the keys of the machine-readable formats
+    pub fn name(self) -> String {
+        return self.to_possible_value().map_or(String::new(), |v| v.get_name().to_string());
+    }
+
+    /// Whether the column holds a raw figure rather than one derived from the Total
+    pub fn is_raw(self) -> bool {
+        return matches!(self, Self::Path | Self::Files | Self::Dirs | Self::Size);
+    }
+
+    /// Whether the column is aligned to the left
+    pub fn is_left(self) -> bool {
+        return matches!(self, Self::Path | Self::Bar);
+    }
+
+    /// The share of the counter in the Total, in 0..=100
+    fn share(self, cnt: &Counter, total: Totals) -> f64 {
+        return match self {
+            Self::PctFiles => op::percent(cnt.n_files, total.0),
+            Self::PctSize => op::percent(cnt.size(), total.2),
+            Self::Bar if cnt.with_size => op::percent(cnt.size(), total.2),
+            Self::Bar => op::percent(cnt.n_files, total.0),
+            _ => 0.0,
+        };
+    }
+
+    /// Render the cell of a counter
+    pub fn cell(self, cnt: &Counter, total: Totals) -> String {
+        return match self {
+            Self::Path => cnt.dirpath.clone(),
+            Self::Files => op::ctx().count(cnt.n_files),
+            Self::Dirs => op::ctx().count(cnt.n_dirs),
+            Self::Size => cnt.readable_size(),
+            Self::PctFiles | Self::PctSize => format!("{:.1}%", self.share(cnt, total)),
+            Self::Bar => {
+                let n_full = (self.share(cnt, total) / 100.0 * BAR_WIDTH as f64).round();
+                op::fill_char('█', (n_full as usize).min(BAR_WIDTH))
+            }
+        };
+    }
+
+    /// The raw value of the cell to sort by, None for the path
+    pub fn value(self, cnt: &Counter, total: Totals) -> Option<f64> {
+        return match self {
+            Self::Path => None,
+            Self::Files => Some(cnt.n_files as f64),
+            Self::Dirs => Some(cnt.n_dirs as f64),
+            Self::Size => Some(cnt.size() as f64),
+            Self::PctFiles | Self::PctSize | Self::Bar => Some(self.share(cnt, total)),
+        };
+    }
+
+    /// Render the cell of the Total row
+    pub fn total_cell(self, total: Totals) -> String {
+        return match self {
+            Self::Path => String::from("Total"),
+            Self::Files => op::ctx().count(total.0),
+            Self::Dirs => op::ctx().count(total.1),
+            Self::Size => op::ctx().size(total.2),
+            Self::PctFiles | Self::PctSize => String::from("100.0%"),
+            Self::Bar => String::new(),
+        };
+    }
+}
+
+// justify the cells by the widths of columns, and join them
+fn join_cells(cells: &[String], columns: &[Column], widths: &[usize]) -> String {
+    let mut fields = vec![];
+    for ((cell, col), &width) in cells.iter().zip(columns).zip(widths) {
+        if col.is_left() {
+            fields.push(op::left_justify(cell, width));
+        } else {
+            fields.push(op::right_justify(cell, width));
+        }
+    }
+    return fields.join("  ").trim_end().to_string();
+}
+
+/// The cells of the head, the rows and the Total row, the Total is only made when there is more than one counter.
+type Cells = (Vec<String>, Vec<Vec<String>>, Option<Vec<String>>);
+
+fn make_cells(counters: &[Counter], columns: &[Column]) -> Cells {
+    let total = Counter::sum(counters);
+    let head = Vec::from_iter(columns.iter().map(|col| col.title().to_string()));
+    let rows = Vec::from_iter(
+        counters
+            .iter()
+            .map(|cnt| Vec::from_iter(columns.iter().map(|col| col.cell(cnt, total)))),
+    );
+    let total_row = match counters.len() > 1 {
+        true => Some(Vec::from_iter(columns.iter().map(|col| col.total_cell(total)))),
+        false => None,
+    };
+    return (head, rows, total_row);
+}
+
+/// Render the counters as a table for the terminal
+pub fn render(counters: &[Counter], columns: &[Column]) -> String {
+    let (head, rows, total_row) = make_cells(counters, columns);
+
+    // calculate the max width of each column from `title`, `total` and `contents`
+    let mut widths = vec![0_usize; columns.len()];
+    for cells in [&head].into_iter().chain(rows.iter()).chain(total_row.iter()) {
+        for (width, cell) in widths.iter_mut().zip(cells) {
+            *width = (*width).max(op::display_width(cell));
+        }
+    }
+    if let Some(idx) = columns.iter().position(|&col| col == Column::Bar) {
+        widths[idx] = widths[idx].max(BAR_WIDTH);
+    }
+
+    // create the output lines from title, content and total
+    let mut lines = vec![op::ctx().title(&join_cells(&head, columns, &widths))];
+    for cells in &rows {
+        lines.push(join_cells(cells, columns, &widths));
+    }
+    if let Some(cells) = total_row {
+        let total_line = join_cells(&cells, columns, &widths);
+        let line_width = widths.iter().sum::<usize>() + 2 * (widths.len() - 1);
+        lines.push(op::fill_char('─', line_width.max(op::display_width(&total_line))));
+        lines.push(op::ctx().strong(&total_line));
+    }
+    return lines.join("\n");
+}
+
+/// Render the counters as a GitHub pipe table, the numeric columns are aligned to the right.
+pub fn to_markdown(counters: &[Counter], columns: &[Column]) -> String {
+    let (head, rows, total_row) = make_cells(counters, columns);
+    let join = |cells: &[String]| {
+        let cells = Vec::from_iter(cells.iter().map(|c| c.replace('|', "\\|")));
+        return format!("| {} |", cells.join(" | "));
+    };
+
+    let aligns = Vec::from_iter(columns.iter().map(|col| match col.is_left() {
+        true => String::from(":---"),
+        false => String::from("---:"),
+    }));
+    let mut lines = vec![join(&head), join(&aligns)];
+    for cells in &rows {
+        lines.push(join(cells));
+    }
+    if let Some(mut cells) = total_row {
+        cells[0] = format!("**{}**", cells[0]);
+        lines.push(join(&cells));
+    }
+    return lines.join("\n");
+}
+
+fn escape_html(text: &str) -> String {
+    return text
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;");
+}
+
+// sort the rows by a column when its header is clicked, the Total row is in `tfoot` so that it stays at the bottom
+const SORT_SCRIPT: &str = r#"<script>
+document.querySelectorAll("th").forEach((th, idx) => {
+  th.addEventListener("click", () => {
+    const tbody = th.closest("table").tBodies[0];
+    const key = (row) => {
+      const cell = row.cells[idx];
+      return cell.dataset.value !== undefined ? Number(cell.dataset.value) : cell.textContent;
+    };
+    const asc = th.dataset.order !== "asc";
+    th.dataset.order = asc ? "asc" : "desc";
+    const rows = Array.from(tbody.rows).sort((a, b) => {
+      const [ka, kb] = [key(a), key(b)];
+      const cmp = typeof ka === "number" ? ka - kb : ka.localeCompare(kb);
+      return asc ? cmp : -cmp;
+    });
+    rows.forEach((row) => tbody.appendChild(row));
+  });
+});
+</script>"#;
+
+/// Render the counters as a standalone HTML page, the table can be sorted by clicking the header.
+pub fn to_html(counters: &[Counter], columns: &[Column]) -> String {
+    let (head, rows, total_row) = make_cells(counters, columns);
+    let total = Counter::sum(counters);
+    let td = |col: &Column, cell: &str, value: Option<f64>| {
+        let align = if col.is_left() { "left" } else { "right" };
+        let value = value.map_or(String::new(), |v| format!(" data-value=\"{}\"", v));
+        return format!("<td style=\"text-align: {}\"{}>{}</td>", align, value, escape_html(cell));
+    };
+
+    let mut lines = vec![
+        String::from("<!DOCTYPE html>"),
+        String::from("<html>"),
+        String::from("<head>"),
+        String::from("<meta charset=\"utf-8\">"),
+        String::from("<title>fcnt</title>"),
+        String::from("<style>table { border-collapse: collapse; } th, td { padding: 2px 8px; } th { cursor: pointer; }</style>"),
+        String::from("</head>"),
+        String::from("<body>"),
+        String::from("<table>"),
+    ];
+    let ths = Vec::from_iter(head.iter().map(|cell| format!("<th>{}</th>", escape_html(cell))));
+    lines.push(format!("<thead><tr>{}</tr></thead>", ths.join("")));
+    lines.push(String::from("<tbody>"));
+    for (cnt, cells) in counters.iter().zip(&rows) {
+        let tds = Vec::from_iter(
+            columns
+                .iter()
+                .zip(cells)
+                .map(|(col, cell)| td(col, cell, col.value(cnt, total))),
+        );
+        lines.push(format!("<tr>{}</tr>", tds.join("")));
+    }
+    lines.push(String::from("</tbody>"));
+    if let Some(cells) = total_row {
+        let tds = Vec::from_iter(columns.iter().zip(&cells).map(|(col, cell)| td(col, cell, None)));
+        lines.push(format!("<tfoot><tr>{}</tr></tfoot>", tds.join("")));
+    }
+    lines.push(String::from("</table>"));
+    lines.push(SORT_SCRIPT.to_string());
+    lines.push(String::from("</body>"));
+    lines.push(String::from("</html>"));
+    return lines.join("\n");
+}
+
+#[cfg(test)]
+fn test_counters() -> Vec<Counter> {
+    use crate::testutil::counter;
+
+    return vec![counter("a", 30, 2), counter("你好", 10, 0)];
+}
+
+#[test]
+fn test_column_names() {
+    let names = Vec::from_iter(Column::value_variants().iter().map(|c| c.name()));
+    assert_eq!(names, ["path", "files", "dirs", "size", "pct-files", "pct-size", "bar"]);
+    assert_eq!(Column::from_str("pct-size", false), Ok(Column::PctSize));
+    assert_eq!(Column::from_str("n", false), Ok(Column::Path));
+}
+
+#[test]
+fn test_render() {
+    let columns = [Column::Path, Column::Files, Column::Dirs];
+    let counters = test_counters();
+    let expected = [
+        "Path   Files  Dirs",
+        "a/        30     2",
+        "你好/     10     0",
+        "──────────────────",
+        "Total     40     2",
+    ];
+    let output = render(&counters, &columns);
+    assert_eq!(output, expected.join("\n"));
+
+    assert_eq!(render(&counters[..1], &columns).lines().count(), 2);
+}
+
+#[test]
+fn test_share_columns() {
+    let columns = [Column::Path, Column::PctFiles, Column::Bar];
+    let output = render(&test_counters(), &columns);
+    let lines = Vec::from_iter(output.lines());
+    assert_eq!(lines[0], "Path   %Files  Share");
+    assert_eq!(lines[1], format!("a/      75.0%  {}", "█".repeat(15)));
+    assert_eq!(lines[2], format!("你好/   25.0%  {}", "█".repeat(5)));
+    assert_eq!(lines[4], "Total  100.0%");
+}
+
+#[test]
+fn test_markdown() {
+    let columns = [Column::Path, Column::Files, Column::PctFiles];
+    let mut counters = test_counters();
+    counters[0].dirpath = String::from("a|b/");
+    let expected = [
+        "| Path | Files | %Files |",
+        "| :--- | ---: | ---: |",
+        "| a\\|b/ | 30 | 75.0% |",
+        "| 你好/ | 10 | 25.0% |",
+        "| **Total** | 40 | 100.0% |",
+    ];
+    assert_eq!(to_markdown(&counters, &columns), expected.join("\n"));
+}
+
+#[test]
+fn test_html() {
+    let columns = [Column::Path, Column::Files];
+    let mut counters = test_counters();
+    counters[0].dirpath = String::from("<a>/");
+    let output = to_html(&counters, &columns);
+    assert!(output.starts_with("<!DOCTYPE html>"));
+    assert!(output.contains("<thead><tr><th>Path</th><th>Files</th></tr></thead>"));
+    assert!(output.contains(
+        "<tr><td style=\"text-align: left\">&lt;a&gt;/</td><td style=\"text-align: right\" data-value=\"30\">30</td></tr>"
+    ));
+    assert!(output.contains("<tfoot><tr><td style=\"text-align: left\">Total</td>"));
+}
@@ -0,0 +1,232 @@
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::fs::Metadata;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+/// The placeholders which can be used in a template
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Path,
+    RelPath,
+    Name,
+    Ext,
+    Size,
+    Mtime,
+    Mode,
+    Uid,
+    Inode,
+    Type,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        return match name {
+            "path" => Some(Self::Path),
+            "relpath" => Some(Self::RelPath),
+            "name" => Some(Self::Name),
+            "ext" => Some(Self::Ext),
+            "size" => Some(Self::Size),
+            "mtime" => Some(Self::Mtime),
+            "mode" => Some(Self::Mode),
+            "uid" => Some(Self::Uid),
+            "inode" => Some(Self::Inode),
+            "type" => Some(Self::Type),
+            _ => None,
+        };
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Field(Field),
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    msg: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl Error for ParseError {}
+
+/// An entry found during the traversal
+pub struct Entry<'a> {
+    pub path: &'a Path,
+    /// the root directory which the entry was found in
+    pub root: &'a Path,
+    /// one of "file", "dir" and "symlink"
+    pub ftype: &'a str,
+    pub meta: &'a Metadata,
+}
+
+/// A find-style output template, such as "{size}\t{path}\n"
+#[derive(Debug, Clone)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Parse the template.
+    /// The placeholders are wrapped in braces, "{{" and "}}" stand for the literal braces,
+    /// and the escapes "\n", "\t", "\0" and "\\" are supported.
+    pub fn parse(tmpl: &str) -> Result<Self, ParseError> {
+        let mut segments = vec![];
+        let mut text = String::new();
+        let mut chars = tmpl.chars();
+
+        while let Some(chr) = chars.next() {
+            match chr {
+                '\\' => match chars.next() {
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    Some('0') => text.push('\0'),
+                    Some('\\') => text.push('\\'),
+                    Some(other) => {
+                        text.push('\\');
+                        text.push(other);
+                    }
+                    None => text.push('\\'),
+                },
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let Some(end) = rest.find('}') else {
+                        return Err(ParseError {
+                            msg: String::from("unclosed placeholder"),
+                        });
+                    };
+                    let name = &rest[..end];
+                    let Some(field) = Field::from_name(name) else {
+                        return Err(ParseError {
+                            msg: format!("unknown placeholder '{}'", name),
+                        });
+                    };
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Field(field));
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => {
+                    return Err(ParseError {
+                        msg: String::from("unmatched '}'"),
+                    })
+                }
+                _ => text.push(chr),
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+
+        return Ok(Self { segments });
+    }
+
+    // the paths and names are rendered as their raw bytes, so any file name is kept intact
+    fn render_field(field: Field, entry: &Entry) -> Vec<u8> {
+        return match field {
+            Field::Path => entry.path.as_os_str().as_bytes().to_vec(),
+            Field::RelPath => match entry.path.strip_prefix(entry.root) {
+                Ok(relpath) => relpath.as_os_str().as_bytes().to_vec(),
+                Err(_) => entry.path.as_os_str().as_bytes().to_vec(),
+            },
+            Field::Name => match entry.path.file_name() {
+                Some(name) => name.as_bytes().to_vec(),
+                None => vec![],
+            },
+            Field::Ext => match entry.path.extension() {
+                Some(ext) => ext.as_bytes().to_vec(),
+                None => vec![],
+            },
+            Field::Size => entry.meta.size().to_string().into_bytes(),
+            Field::Mtime => entry.meta.mtime().to_string().into_bytes(),
+            Field::Mode => format!("{:o}", entry.meta.mode() & 0o7777).into_bytes(),
+            Field::Uid => entry.meta.uid().to_string().into_bytes(),
+            Field::Inode => entry.meta.ino().to_string().into_bytes(),
+            Field::Type => entry.ftype.as_bytes().to_vec(),
+        };
+    }
+
+    /// Render the template with an entry, the output is in bytes since the paths may not be UTF-8
+    pub fn render(&self, entry: &Entry) -> Vec<u8> {
+        let mut output = vec![];
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => output.extend_from_slice(text.as_bytes()),
+                Segment::Field(field) => output.extend(Self::render_field(*field, entry)),
+            }
+        }
+        return output;
+    }
+}
+
+#[test]
+fn test_parse() {
+    let tmpl = Template::parse("{{{size}}}\\t{path}\\n").unwrap();
+    assert_eq!(
+        tmpl.segments,
+        vec![
+            Segment::Text(String::from("{")),
+            Segment::Field(Field::Size),
+            Segment::Text(String::from("}\t")),
+            Segment::Field(Field::Path),
+            Segment::Text(String::from("\n")),
+        ]
+    );
+
+    assert!(Template::parse("{path").is_err());
+    assert!(Template::parse("path}").is_err());
+    assert!(Template::parse("{unknown}").is_err());
+}
+
+#[test]
+fn test_render() {
+    use std::ffi::OsStr;
+
+    let tmp = crate::testutil::TempDir::new("template");
+    let root = tmp.path();
+    let path = root.join("sub/hello.txt");
+    std::fs::create_dir_all(root.join("sub")).unwrap();
+    std::fs::write(&path, "hello").unwrap();
+    let meta = std::fs::symlink_metadata(&path).unwrap();
+
+    let entry = Entry {
+        path: &path,
+        root,
+        ftype: "file",
+        meta: &meta,
+    };
+    let tmpl = Template::parse("{type} {relpath} {name} {ext} {size}\\0").unwrap();
+    assert_eq!(tmpl.render(&entry), b"file sub/hello.txt hello.txt txt 5\0");
+
+    let tmpl = Template::parse("{inode}:{uid}").unwrap();
+    assert_eq!(tmpl.render(&entry), format!("{}:{}", meta.ino(), meta.uid()).into_bytes());
+
+    // the non-UTF-8 names are kept as they are
+    let path = root.join(OsStr::from_bytes(b"bad\xffname.log"));
+    std::fs::write(&path, "").unwrap();
+    let meta = std::fs::symlink_metadata(&path).unwrap();
+    let entry = Entry {
+        path: &path,
+        root,
+        ftype: "file",
+        meta: &meta,
+    };
+    let tmpl = Template::parse("{relpath}\0").unwrap();
+    assert_eq!(tmpl.render(&entry), b"bad\xffname.log\0");
+}
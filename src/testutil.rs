@@ -0,0 +1,40 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::walker::Counter;
+
+/// Make a counter of the path with the numbers of files and dirs
+pub fn counter(path: &str, n_files: u64, n_dirs: u64) -> Counter {
+    let mut cnt = Counter::new(Path::new(path), false);
+    cnt.n_files = n_files;
+    cnt.n_dirs = n_dirs;
+    return cnt;
+}
+
+/// A temporary directory for the fixtures, it's removed when dropped,
+/// so a failing test doesn't leave it behind.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Create an empty directory named after the test and the process
+    pub fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("fcnt-test-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).expect("create temp dir err");
+        return Self { path };
+    }
+
+    pub fn path(&self) -> &Path {
+        return &self.path;
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+
+use crate::output as op;
+use crate::walker::Counter;
+
+/// A directory in the tree, with the total figures of its subtree
+struct Node {
+    name: String,
+    total: Counter,
+    children: Vec<Node>,
+}
+
+/// The options of the tree view
+pub struct TreeOptions {
+    /// display the size, and use it as the metric of percentages and bars
+    pub with_size: bool,
+    /// display the number of directories
+    pub with_dirs: bool,
+    /// the max depth of the displayed directories, the root is at depth 0
+    pub depth: Option<usize>,
+    /// the children whose share of the parent is less than this percentage are collapsed
+    pub min_percent: f64,
+}
+
+// get the parent dirpath of a dirpath, both of them end with a separator
+fn parent_of(dirpath: &str) -> Option<String> {
+    let (parent, _) = dirpath.strip_suffix('/')?.rsplit_once('/')?;
+    return Some(format!("{}/", parent));
+}
+
+impl Node {
+    fn build(
+        name: String,
+        dirpath: &str,
+        dirs: &HashMap<&str, &Counter>,
+        children_of: &HashMap<String, Vec<&str>>,
+        with_size: bool,
+    ) -> Self {
+        let mut total = match dirs.get(dirpath) {
+            Some(&cnt) => cnt.clone(),
+            None => Counter::new(dirpath.as_ref(), with_size),
+        };
+        total.dirpath = dirpath.to_string();
+
+        let mut children = vec![];
+        for &child in children_of.get(dirpath).into_iter().flatten() {
+            let name = child[dirpath.len()..].to_string();
+            let node = Self::build(name, child, dirs, children_of, with_size);
+            total.merge(&node.total);
+            children.push(node);
+        }
+        return Self {
+            name,
+            total,
+            children,
+        };
+    }
+
+    // a root without the traversed directories, such as a regular file or the row collapsed by `--limit`,
+    // is a leaf made of its own figures
+    fn leaf(cnt: &Counter) -> Self {
+        return Self {
+            name: cnt.dirpath.clone(),
+            total: cnt.clone(),
+            children: vec![],
+        };
+    }
+
+    fn metric(&self, with_size: bool) -> u64 {
+        return if with_size {
+            self.total.size()
+        } else {
+            self.total.n_files
+        };
+    }
+}
+
+/// A line of the tree view
+struct Row {
+    tree: String,
+    files: String,
+    dirs: String,
+    size: String,
+    percent: String,
+    // the share of the largest sibling, in 0..=1
+    bar: f64,
+}
+
+// make the rows of the children of a node, the small children are collapsed into one row
+fn make_rows(node: &Node, prefix: &str, depth: usize, opts: &TreeOptions, rows: &mut Vec<Row>) {
+    if opts.depth.is_some_and(|max_depth| depth >= max_depth) {
+        return;
+    }
+
+    let whole = node.metric(opts.with_size);
+    let mut children = Vec::from_iter(node.children.iter());
+    children.sort_by(|a, b| {
+        let (ma, mb) = (a.metric(opts.with_size), b.metric(opts.with_size));
+        mb.cmp(&ma).then_with(|| a.name.cmp(&b.name))
+    });
+    let n_big = children
+        .iter()
+        .take_while(|c| op::percent(c.metric(opts.with_size), whole) >= opts.min_percent)
+        .count();
+    let (big, small) = children.split_at(n_big);
+    let largest = children.first().map_or(0, |c| c.metric(opts.with_size));
+
+    for (idx, child) in big.iter().enumerate() {
+        let is_last = idx + 1 == big.len() && small.is_empty();
+        let connector = if is_last { "└── " } else { "├── " };
+        let metric = child.metric(opts.with_size);
+        rows.push(Row {
+            tree: format!("{}{}{}", prefix, connector, child.name),
+            files: op::ctx().count(child.total.n_files),
+            dirs: op::ctx().count(child.total.n_dirs),
+            size: child.total.readable_size(),
+            percent: format!("{:.1}%", op::percent(metric, whole)),
+            bar: op::percent(metric, largest) / 100.0,
+        });
+        let sub_prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
+        make_rows(child, &sub_prefix, depth + 1, opts, rows);
+    }
+
+    if !small.is_empty() {
+        let mut others = Counter::new("".as_ref(), opts.with_size);
+        small.iter().for_each(|c| others.merge(&c.total));
+        let metric = if opts.with_size { others.size() } else { others.n_files };
+        rows.push(Row {
+            tree: format!("{}└── ({} smaller)", prefix, small.len()),
+            files: op::ctx().count(others.n_files),
+            dirs: op::ctx().count(others.n_dirs),
+            size: others.readable_size(),
+            percent: format!("{:.1}%", op::percent(metric, whole)),
+            bar: op::percent(metric, largest) / 100.0,
+        });
+    }
+}
+
+fn make_bar(share: f64, width: usize) -> String {
+    let n_full = (share * width as f64).round() as usize;
+    return op::fill_char('█', n_full.min(width));
+}
+
+/// Output the roots as trees, `dirs` are the direct counters of each traversed directory.
+pub fn output(counters: &[Counter], dirs: &[Counter], opts: &TreeOptions) {
+    println!("{}", render(counters, dirs, opts));
+}
+
+// render the roots as trees
+fn render(counters: &[Counter], dirs: &[Counter], opts: &TreeOptions) -> String {
+    let dir_map = HashMap::<&str, &Counter>::from_iter(dirs.iter().map(|c| (c.dirpath.as_str(), c)));
+    let mut children_of = HashMap::<String, Vec<&str>>::new();
+    for cnt in dirs {
+        if let Some(parent) = parent_of(&cnt.dirpath) {
+            children_of.entry(parent).or_default().push(&cnt.dirpath);
+        }
+    }
+
+    // the roots are the siblings of each other
+    let total = Counter::sum(counters);
+    let whole = if opts.with_size { total.2 } else { total.0 };
+    let mut rows = vec![];
+    let roots = Vec::from_iter(counters.iter().map(|c| match dir_map.contains_key(c.dirpath.as_str()) {
+        true => Node::build(c.dirpath.clone(), &c.dirpath, &dir_map, &children_of, opts.with_size),
+        false => Node::leaf(c),
+    }));
+    let largest = roots.iter().map(|r| r.metric(opts.with_size)).max().unwrap_or(0);
+    for root in &roots {
+        let metric = root.metric(opts.with_size);
+        rows.push(Row {
+            tree: root.name.clone(),
+            files: op::ctx().count(root.total.n_files),
+            dirs: op::ctx().count(root.total.n_dirs),
+            size: root.total.readable_size(),
+            percent: format!("{:.1}%", op::percent(metric, whole)),
+            bar: op::percent(metric, largest) / 100.0,
+        });
+        make_rows(root, "", 0, opts, &mut rows);
+    }
+
+    // calculate the width of each column, the bar takes the rest of the terminal
+    let title = ["Path", "Files", "Dirs", "Size", "%"];
+    let width = |f: &dyn Fn(&Row) -> &String, title: &str| {
+        rows.iter().map(|r| op::display_width(f(r))).max().unwrap_or(0).max(title.len())
+    };
+    let w_tree = width(&|r| &r.tree, title[0]);
+    let w_files = width(&|r| &r.files, title[1]);
+    let w_dirs = width(&|r| &r.dirs, title[2]);
+    let w_size = width(&|r| &r.size, title[3]);
+    let w_pct = width(&|r| &r.percent, title[4]);
+    let used = w_tree + w_files + w_pct + 6
+        + if opts.with_dirs { w_dirs + 2 } else { 0 }
+        + if opts.with_size { w_size + 2 } else { 0 };
+    let w_bar = op::terminal_width().saturating_sub(used + 1).min(50);
+
+    let join = |tree: String, files: &str, dirs: &str, size: &str, pct: &str, bar: String| {
+        let mut fields = vec![tree, op::right_justify(&files, w_files)];
+        if opts.with_dirs {
+            fields.push(op::right_justify(&dirs, w_dirs));
+        }
+        if opts.with_size {
+            fields.push(op::right_justify(&size, w_size));
+        }
+        fields.push(op::right_justify(&pct, w_pct));
+        if w_bar >= 5 {
+            fields.push(bar);
+        }
+        return fields.join("  ").trim_end().to_string();
+    };
+
+    let mut lines = vec![op::ctx().title(&join(
+        op::left_justify(&title[0], w_tree),
+        title[1],
+        title[2],
+        title[3],
+        title[4],
+        op::spaces(w_bar),
+    ))];
+    for row in rows {
+        let bar = op::ctx().info(&make_bar(row.bar, w_bar));
+        let tree = op::left_justify(&row.tree, w_tree);
+        lines.push(join(tree, &row.files, &row.dirs, &row.size, &row.percent, bar));
+    }
+    return lines.join("\n");
+}
+
+#[test]
+fn test_parent_of() {
+    assert_eq!(parent_of("a/b/"), Some(String::from("a/")));
+    assert_eq!(parent_of("/usr/"), Some(String::from("/")));
+    assert_eq!(parent_of("./a/"), Some(String::from("./")));
+    assert_eq!(parent_of("a/"), None);
+    assert_eq!(parent_of("/"), None);
+}
+
+#[test]
+fn test_tree_rows() {
+    use crate::testutil::counter;
+
+    let dirs = vec![
+        counter("r", 1, 0),
+        counter("r/big", 60, 0),
+        counter("r/big/sub", 20, 0),
+        counter("r/mid", 18, 0),
+        counter("r/tiny1", 1, 0),
+        counter("r/tiny2", 0, 0),
+    ];
+    let dir_map = HashMap::from_iter(dirs.iter().map(|c| (c.dirpath.as_str(), c)));
+    let mut children_of = HashMap::<String, Vec<&str>>::new();
+    for cnt in &dirs {
+        if let Some(parent) = parent_of(&cnt.dirpath) {
+            children_of.entry(parent).or_default().push(&cnt.dirpath);
+        }
+    }
+    let root = Node::build(String::from("r/"), "r/", &dir_map, &children_of, false);
+    assert_eq!(root.total.n_files, 100);
+
+    let opts = TreeOptions {
+        with_size: false,
+        with_dirs: false,
+        depth: None,
+        min_percent: 5.0,
+    };
+    let mut rows = vec![];
+    make_rows(&root, "", 0, &opts, &mut rows);
+    let trees = Vec::from_iter(rows.iter().map(|r| r.tree.as_str()));
+    assert_eq!(
+        trees,
+        vec!["├── big/", "│   └── sub/", "├── mid/", "└── (2 smaller)"]
+    );
+    assert_eq!(rows[0].percent, "80.0%");
+    assert_eq!(rows[0].bar, 1.0);
+    assert!((rows[2].bar - 0.225).abs() < 1e-9);
+    assert_eq!(rows[3].files, "1");
+
+    let opts = TreeOptions { depth: Some(1), ..opts };
+    let mut rows = vec![];
+    make_rows(&root, "", 0, &opts, &mut rows);
+    assert_eq!(rows.len(), 3);
+}
+
+#[test]
+fn test_leaf_roots() {
+    use crate::testutil::counter;
+
+    // a regular file given as a root and the collapsed row have no traversed directories
+    let mut file = counter("r/f", 1, 0);
+    file.dirpath = String::from("r/f");
+    let counters = Counter::collapse(vec![counter("r", 3, 1), file, counter("s", 2, 0)], Some(2));
+    let dirs = vec![counter("r", 1, 1), counter("r/a", 2, 0)];
+    let opts = TreeOptions {
+        with_size: false,
+        with_dirs: true,
+        depth: None,
+        min_percent: 1.0,
+    };
+    let output = render(&counters, &dirs, &opts);
+    let lines = Vec::from_iter(output.lines().map(|l| l.split_whitespace().take(4).collect::<Vec<_>>()));
+    assert_eq!(lines[0], ["Path", "Files", "Dirs", "%"]);
+    assert_eq!(lines[1], ["r/", "3", "1", "50.0%"]);
+    assert_eq!(lines[2], ["└──", "a/", "2", "0"]);
+    assert_eq!(lines[3], ["r/f", "1", "0", "16.7%"]);
+    assert_eq!(lines[4], ["(1", "others)", "2", "0"]);
+}
@@ -1,382 +1,918 @@
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::io::Result;
-#[cfg(target_os = "linux")]
-use std::os::linux::fs::MetadataExt;
-#[cfg(target_os = "macos")]
-use std::os::macos::fs::MetadataExt;
-#[cfg(target_os = "unix")]
+use std::io::{self, Result, Write};
 use std::os::unix::fs::MetadataExt;
-use std::path::{PathBuf, MAIN_SEPARATOR_STR};
+use std::path::{Path, PathBuf, MAIN_SEPARATOR_STR};
 use std::sync::mpsc::channel as s_channel;
 use std::sync::Arc;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::RwLock;
 use std::thread;
 use std::time::Duration;
 
 use flume::unbounded as m_channel;
 use regex::Regex;
 
+use crate::cache::Cache;
 use crate::output as op;
+use crate::table::{self, Column};
+use crate::template::{Entry, Template};
 
+type DirId = (u64, u64);
 type DirList = Vec<PathBuf>;
 type SizeMap = HashMap<u64, u64>;
-type DirDetail = (DirList, Counter);
-type Lengths = (usize, usize, usize, usize);
+pub type DirDetail = (DirList, Counter);
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Counter {
     pub dirpath: String,
     pub n_files: u64,
     pub n_dirs: u64,
-    pub sz_map: Option<SizeMap>,
+    pub with_size: bool,
+    // the figures are already included in another counter
+    pub overlapped: bool,
+    // the number of the counters collapsed into this one by `--limit`, its dirpath is not a path then
+    pub collapsed: usize,
+    // total size of the files which have only one hard link
+    sz_total: u64,
+    // inode -> size, only for the files which have more than one hard link
+    sz_links: SizeMap,
 }
 
 impl Counter {
-    const SZ_UNIT: [&'static str; 7] = ["B", "K", "M", "G", "T", "P", "E"];
-
     /// Create a new Counter
-    pub fn new(dirpath: &PathBuf, with_size: bool) -> Self {
+    pub fn new(dirpath: &Path, with_size: bool) -> Self {
+        let mut dirpath = dirpath.to_string_lossy().to_string();
+        if !dirpath.ends_with(MAIN_SEPARATOR_STR) {
+            dirpath.push_str(MAIN_SEPARATOR_STR);
+        }
         return Self {
-            dirpath: dirpath.to_string_lossy().to_string() + MAIN_SEPARATOR_STR,
+            dirpath,
             n_files: 0,
             n_dirs: 0,
-
-            sz_map: match with_size {
-                true => Some(SizeMap::new()),
-                false => None,
-            },
+            with_size,
+            overlapped: false,
+            collapsed: 0,
+            sz_total: 0,
+            sz_links: SizeMap::new(),
         };
     }
 
-    // get the file size from Metadata
-    fn file_size(metadata: &fs::Metadata) -> u64 {
-        let sz = metadata.st_size() as f64;
-        let blksz = metadata.st_blksize() as f64;
+    /// Create a Counter of a regular file given as a root, its path has no trailing separator.
+    /// The file is checked by its name against `-r` and `--exclude-set`,
+    /// but it's counted even if it's hidden since it's given explicitly.
+    pub fn from_file(path: &Path, meta: &fs::Metadata, opts: &Options) -> Self {
+        let mut cnt = Self::new(path, opts.with_size);
+        cnt.dirpath = path.to_string_lossy().to_string();
+        if path.file_name().is_some_and(|name| is_excluded(name, opts) || !is_matched(&opts.filter, name)) {
+            return cnt;
+        }
+        cnt.n_files = 1;
+        cnt.add_size(meta.ino(), meta.nlink(), Self::file_size(meta, opts.apparent_size));
+        if let Some(ref template) = opts.template {
+            let entry = Entry {
+                path,
+                root: path,
+                ftype: "file",
+                meta,
+            };
+            let _ = io::stdout().lock().write_all(&template.render(&entry));
+        }
+        return cnt;
+    }
+
+    // get the file size from Metadata, it's rounded up to the block size unless `apparent`
+    fn file_size(metadata: &fs::Metadata, apparent: bool) -> u64 {
+        if apparent {
+            return metadata.size();
+        }
+        let sz = metadata.size() as f64;
+        let blksz = metadata.blksize() as f64;
         return (blksz * (sz / blksz).ceil()) as u64;
     }
 
+    // add the size of a file.
+    // only the hard linked files need to be deduplicated by inode,
+    // the others are summed directly to keep the memory usage low.
+    fn add_size(&mut self, inode: u64, n_links: u64, size: u64) {
+        if !self.with_size {
+            return;
+        }
+        if n_links > 1 {
+            self.sz_links.insert(inode, size);
+        } else {
+            self.sz_total += size;
+        }
+    }
+
     // calculate the total size of files in dirpath
     pub fn size(&self) -> u64 {
-        match self.sz_map.as_ref() {
-            Some(mp) => mp.values().sum(),
-            None => 0,
-        }
+        return self.sz_total + self.sz_links.values().sum::<u64>();
     }
 
     fn add_unit_to_size(size: u64) -> String {
-        let mut sz = size as f64;
-        let mut str_sz = String::new();
-
-        for unit in Self::SZ_UNIT {
-            if sz >= 1024.0 {
-                sz = sz / 1024.0;
-            } else {
-                if sz.fract() < 0.05 {
-                    str_sz = format!("{:.0}{}", sz, unit);
-                } else {
-                    str_sz = format!("{:.1}{}", sz, unit);
-                }
-                break;
-            }
-        }
-        return str_sz;
+        return op::ctx().size(size);
     }
 
     // make "size" more readable
-    fn readable_size(&self) -> String {
+    pub fn readable_size(&self) -> String {
         return Self::add_unit_to_size(self.size());
     }
 
     // merge from anther Counter
-    fn merge(&mut self, other: &Self) {
-        if other.dirpath.starts_with(&self.dirpath) {
-            self.n_files += other.n_files;
-            self.n_dirs += other.n_dirs;
-            if let Some(sz_mp) = self.sz_map.as_mut() {
-                sz_mp.extend(other.sz_map.as_ref().unwrap().iter());
-            }
+    pub fn merge(&mut self, other: &Self) {
+        self.n_files += other.n_files;
+        self.n_dirs += other.n_dirs;
+        if self.with_size {
+            self.sz_total += other.sz_total;
+            self.sz_links.extend(other.sz_links.iter());
         }
     }
 
-    // get the length of each field for display
-    fn lengths(&self) -> Lengths {
-        return (
-            op::display_width(&self.dirpath),
-            self.n_files.to_string().len(),
-            self.n_dirs.to_string().len(),
-            self.readable_size().len(),
-        );
-    }
-
-    fn join_fields(
-        fields: Vec<&dyn ToString>,
-        with_dir: bool,
-        with_size: bool,
-        lens: Lengths,
-    ) -> String {
-        let mut str_fields = vec![
-            op::left_justify(fields[0], lens.0),
-            op::right_justify(fields[1], lens.1),
-        ];
-
-        if with_dir {
-            str_fields.push(op::right_justify(fields[2], lens.2));
-        }
-
-        if with_size {
-            str_fields.push(op::right_justify(fields[3], lens.3));
+    /// Sum up the files, dirs and size of the counters, the overlapped ones are skipped.
+    pub fn sum(counters: &[Self]) -> (u64, u64, u64) {
+        let mut sum = (0_u64, 0_u64, 0_u64);
+        for c in counters.iter().filter(|c| !c.overlapped) {
+            sum.0 += c.n_files;
+            sum.1 += c.n_dirs;
+            sum.2 += c.size();
         }
-
-        return str_fields.join("  ");
+        return sum;
     }
 
-    fn to_string(&self, with_dir: bool, lens: Lengths) -> String {
-        let size = self.readable_size();
-        let fields: Vec<&dyn ToString> = vec![&self.dirpath, &self.n_files, &self.n_dirs, &size];
-        let with_size = self.sz_map != None;
-        return Self::join_fields(fields, with_dir, with_size, lens);
-    }
+    /// Keep the first `limit` counters, and collapse the others into one counter.
+    /// The overlapped ones are not collapsed, so the total stays the same.
+    pub fn collapse(mut counters: Vec<Self>, limit: Option<usize>) -> Vec<Self> {
+        let limit = match limit {
+            Some(limit) if limit < counters.len() => limit,
+            _ => return counters,
+        };
 
-    fn make_head_line(with_dir: bool, with_size: bool, lens: Lengths) -> String {
-        let fields: Vec<&dyn ToString> = vec![&"Path", &"Files", &"Dirs", &"Size"];
-        return op::title(&Self::join_fields(fields, with_dir, with_size, lens));
+        let rest = counters.split_off(limit);
+        let mut others = Counter::new(Path::new(""), rest[0].with_size);
+        others.dirpath = format!("({} others)", rest.len());
+        others.collapsed = rest.len();
+        for cnt in rest.iter().filter(|c| !c.overlapped) {
+            others.merge(cnt);
+        }
+        counters.push(others);
+        return counters;
     }
 
-    fn make_total_line(
-        total: (String, String, String, String),
-        with_dir: bool,
-        with_size: bool,
-        lens: Lengths,
-    ) -> String {
-        let fields: Vec<&dyn ToString> = vec![&total.0, &total.1, &total.2, &total.3];
-        let total_line = Self::join_fields(fields, with_dir, with_size, lens);
-        let hor_line = op::fill_char('─', total_line.len());
-
-        return format!("{}\n{}", hor_line, op::strong(&total_line));
+    /// Output the counters as a table with the given columns
+    pub fn output(counters: &[Self], columns: &[Column]) {
+        println!("{}", table::render(counters, columns));
     }
+}
 
-    fn summarize(counters: &Vec<Self>) -> (String, String, String, String) {
-        let mut sum = (0_u64, 0_u64, 0_u64);
-        for c in counters {
-            sum.0 += c.n_files;
-            sum.1 += c.n_dirs;
-            sum.2 += c.size();
-        }
+/// The options of traversal
+#[derive(Clone)]
+pub struct Options {
+    /// include the hidden files and dirs
+    pub with_hidden: bool,
+    /// count the size of files
+    pub with_size: bool,
+    /// count the apparent size of files rather than the disk usage
+    pub apparent_size: bool,
+    /// only the files whose name matches the filter will be counted
+    pub filter: Option<Regex>,
+    /// only the dirs whose name matches the filter will be counted
+    pub dir_filter: Option<Regex>,
+    /// the files and dirs whose name matches it are ignored, and the dirs are not traversed
+    pub exclude: Option<Regex>,
+    /// display the found entries
+    pub verbose: bool,
+    /// display the found entries with the template
+    pub template: Option<Template>,
+    /// only display the matched files
+    pub list_only: bool,
+    /// traverse the sub-directories
+    pub recursive: bool,
+    /// do not credit the nested roots to the roots containing them
+    pub exclude_nested: bool,
+    /// keep the direct counter of each traversed directory
+    pub keep_dirs: bool,
+    /// reuse the direct counts of the unchanged directories
+    pub cache: Option<Arc<Cache>>,
+}
 
-        return (
-            String::from("Total"),
-            sum.0.to_string(),
-            sum.1.to_string(),
-            Self::add_unit_to_size(sum.2),
-        );
+impl Default for Options {
+    /// The options of a plain `fcnt` run: recursive, without the hidden files and the size
+    fn default() -> Self {
+        return Self {
+            with_hidden: false,
+            with_size: false,
+            apparent_size: false,
+            filter: None,
+            dir_filter: None,
+            exclude: None,
+            verbose: false,
+            template: None,
+            list_only: false,
+            recursive: true,
+            exclude_nested: false,
+            keep_dirs: false,
+            cache: None,
+        };
     }
+}
 
-    fn max_lengths(lens: Vec<Lengths>) -> Lengths {
-        let mut max_lens: Lengths = (0, 0, 0, 0);
-        for (l0, l1, l2, l3) in lens {
-            max_lens.0 = max_lens.0.max(l0);
-            max_lens.1 = max_lens.1.max(l1);
-            max_lens.2 = max_lens.2.max(l2);
-            max_lens.3 = max_lens.3.max(l3);
-        }
-        return max_lens;
+// check whether the name matches the filter, everything matches if there is no filter
+fn is_matched(filter: &Option<Regex>, fname: &OsStr) -> bool {
+    match filter {
+        Some(filter) => filter.is_match(&fname.to_string_lossy()),
+        None => true,
     }
+}
 
-    pub fn output(counters: &Vec<Self>, with_dir: bool, with_size: bool) {
-        let total = if counters.len() > 1 {
-            Self::summarize(counters)
-        } else {
-            (String::new(), String::new(), String::new(), String::new())
-        };
+// check whether an entry matches `--exclude-set`
+fn is_excluded(fname: &OsStr, opts: &Options) -> bool {
+    return opts.exclude.as_ref().is_some_and(|re| re.is_match(&fname.to_string_lossy()));
+}
 
-        // calculate the max value from `title`, `total` and `contents` lengths
-        let title_lens = (4_usize, 5_usize, 4_usize, 4_usize);
-        let total_lens = (total.0.len(), total.1.len(), total.2.len(), total.3.len());
-        let mut lens = Vec::from_iter(counters.iter().map(|c| c.lengths()));
-        lens.append(&mut vec![total_lens, title_lens]);
-        let max_lens = Self::max_lengths(lens);
-
-        // create the output lines from title, content and total
-        let mut lines: Vec<String> = vec![];
-        lines.push(Self::make_head_line(with_dir, with_size, max_lens));
-        for cnt in counters {
-            lines.push(cnt.to_string(with_dir, max_lens));
-        }
+// check whether an entry found by the walk is skipped by its name:
+// the hidden ones unless `with_hidden`, and the excluded ones.
+// the skipped dirs are neither counted nor traversed.
+fn is_ignored(fname: &OsStr, opts: &Options) -> bool {
+    return (!opts.with_hidden && fname.as_encoded_bytes().starts_with(b".")) || is_excluded(fname, opts);
+}
 
-        // output the total only when there is more than one counters
-        if counters.len() > 1 {
-            let total_line = Self::make_total_line(total, with_dir, with_size, max_lens);
-            lines.push(total_line);
-        }
+// check whether the walk of its parent directory counts a regular file by its name,
+// it's used to find out the regular files given as roots which are counted twice
+fn is_counted_file(fname: &OsStr, opts: &Options) -> bool {
+    return !is_ignored(fname, opts) && is_matched(&opts.filter, fname);
+}
 
-        // output
-        println!("{}", lines.join("\n"));
-    }
+/// Traverse the entries of a directory, the found entries are displayed if `verbose` or with the template.
+pub fn walk(dirpath: &Path, root: &Path, opts: &Options) -> Result<DirDetail> {
+    let mut listing = vec![];
+    let result = walk_into(dirpath, root, opts, &mut listing);
+    // the listing is written as raw bytes, since the paths may not be UTF-8
+    let _ = io::stdout().lock().write_all(&listing);
+    return result;
 }
 
-pub fn walk(
-    dirpath: &PathBuf,
-    with_hidden: bool,
-    with_size: bool,
-    filter: Option<Regex>,
-    verbose: bool,
-) -> Result<DirDetail> {
+// traverse the entries of a directory, the displayed entries are written to `listing`
+fn walk_into(dirpath: &Path, root: &Path, opts: &Options, listing: &mut Vec<u8>) -> Result<DirDetail> {
     let mut dirs = DirList::new();
-    let mut cnt = Counter::new(dirpath, with_size);
+    let mut cnt = Counter::new(dirpath, opts.with_size);
 
     for entry in fs::read_dir(dirpath)? {
         let entry = entry?;
         let path = entry.path();
         let fname = entry.file_name();
         let ftype: String;
+        let kind: &str;
 
-        if !with_hidden && fname.to_string_lossy().starts_with('.') {
-            // ignore the hidden files and dirs
+        if is_ignored(&fname, opts) {
+            // ignore the hidden and the excluded files and dirs
             continue;
         } else if path.is_symlink() {
             // The size of symbolic link is 0B.
             // So just increase the num here.
             if path.is_dir() {
+                if !is_matched(&opts.dir_filter, &fname) {
+                    continue;
+                }
                 cnt.n_dirs += 1;
             } else {
                 cnt.n_files += 1;
             }
-            ftype = op::note(&"Symlink");
+            ftype = op::ctx().note(&"Symlink");
+            kind = "symlink";
+            // the symlinks to files are listed only if they match the filter
+            if opts.list_only && (path.is_dir() || !is_matched(&opts.filter, &fname)) {
+                continue;
+            }
         } else if path.is_dir() {
-            cnt.n_dirs += 1;
-            ftype = op::warn(&"Dir");
+            // the unmatched dirs are still traversed
             dirs.push(path.clone());
+            if !is_matched(&opts.dir_filter, &fname) {
+                continue;
+            }
+            cnt.n_dirs += 1;
+            ftype = op::ctx().warn(&op::right_justify(&"Dir", 7));
+            kind = "dir";
+            if opts.list_only {
+                continue;
+            }
         } else {
-            if let Some(ref filter) = filter {
-                if !filter.is_match(fname.to_str().unwrap()) {
-                    continue;
-                }
+            if !is_matched(&opts.filter, &fname) {
+                continue;
             }
 
             cnt.n_files += 1;
-            ftype = op::info(&"File");
-            // count file size and insert into SizeMap
-            if let Some(mp) = cnt.sz_map.as_mut() {
+            ftype = op::ctx().info(&op::right_justify(&"File", 7));
+            kind = "file";
+            // count file size
+            if opts.with_size {
                 let meta = entry.metadata()?;
-                mp.insert(meta.st_ino(), Counter::file_size(&meta));
+                cnt.add_size(meta.ino(), meta.nlink(), Counter::file_size(&meta, opts.apparent_size));
             }
         }
-        if verbose {
-            println!("{:>18} > {}", ftype, path.to_string_lossy());
+        if let Some(ref template) = opts.template {
+            let meta = entry.metadata()?;
+            let entry = Entry {
+                path: &path,
+                root,
+                ftype: kind,
+                meta: &meta,
+            };
+            listing.extend(template.render(&entry));
+        } else if opts.verbose {
+            let mode = || entry.metadata().ok().map(|m| m.mode());
+            let line = format!("{} > {}\n", ftype, op::ctx().entry_path(&path, kind, mode));
+            listing.extend_from_slice(line.as_bytes());
         }
     }
 
     return Ok((dirs, cnt));
 }
 
-pub fn parallel_walk(
-    dirlist: Vec<PathBuf>,
-    with_hidden: bool,
-    with_size: bool,
-    filter: Option<Regex>,
-    verbose: bool,
-    n_thread: usize,
-) -> Vec<Counter> {
-    let (path_tx, path_rx) = m_channel::<PathBuf>();
-    let (cnt_tx, cnt_rx) = s_channel::<Counter>();
-    let mut counters = Vec::from_iter(dirlist.iter().map(|p| Counter::new(p, with_size)));
-    let stat_locker = Arc::new(Mutex::new(HashMap::new()));
+// walk a directory, or reuse its cached result if it's unchanged
+fn walk_cached(dirpath: &Path, root: &Path, opts: &Options) -> Result<DirDetail> {
+    let Some(cache) = &opts.cache else {
+        return walk(dirpath, root, opts);
+    };
+    // the metadata is read before the traversal, so the changes during it invalidate the entry
+    let meta = fs::metadata(dirpath)?;
+    // the entries are keyed by the canonical path, so the same directory is found from any root
+    let key = fs::canonicalize(dirpath)?;
+    if let Some(detail) = cache.lookup(&key, dirpath, &meta) {
+        return Ok(detail);
+    }
+    let (sub_dirs, cnt) = walk(dirpath, root, opts)?;
+    cache.store(key, &meta, &sub_dirs, &cnt);
+    return Ok((sub_dirs, cnt));
+}
 
-    // send dirlist to path channel
-    for path in dirlist {
-        path_tx.send(path.clone()).expect("send path err");
+// get the identity of a directory on the filesystem
+fn dir_id(path: &Path) -> Option<DirId> {
+    return fs::metadata(path).ok().map(|m| (m.dev(), m.ino()));
+}
+
+/// The result of a traversed directory
+#[derive(Debug, Clone)]
+pub struct Visit {
+    /// the index of the root it belongs to
+    pub r_idx: usize,
+    /// the direct counter of the directory
+    pub cnt: Counter,
+    /// the nested roots among its sub-directories, they are traversed by themselves
+    pub reached: Vec<usize>,
+    /// the regular files given as roots which it has counted
+    pub counted: Vec<usize>,
+}
+
+/// How the roots overlap with each other
+#[derive(Default)]
+pub struct Overlaps {
+    // the index of the first root which is the same directory as this one
+    aliases: Vec<Option<usize>>,
+    // the number of components of the canonical path of each root
+    depths: Vec<usize>,
+    // the roots which are inside another root
+    nested: HashMap<DirId, usize>,
+    // the regular files given as roots which are inside another root:
+    // the identity of the parent directory -> (the root index, the file name)
+    inner_files: HashMap<DirId, Vec<(usize, OsString)>>,
+    // the identity of each first root -> its index
+    firsts: HashMap<DirId, usize>,
+    // the canonical path of each first root -> (its index, its identity),
+    // it's ordered so that the roots inside a path are found in a range
+    canonicals: BTreeMap<PathBuf, (usize, DirId)>,
+}
+
+impl Overlaps {
+    pub fn new(dirlist: &[PathBuf]) -> Self {
+        let mut overlaps = Self::default();
+        for path in dirlist {
+            overlaps.add(path);
+        }
+        return overlaps;
     }
 
+    /// Add the next root, return the indices of the previous roots containing it.
+    /// The invalid roots overlap nothing.
+    pub fn add(&mut self, path: &Path) -> Vec<usize> {
+        let idx = self.aliases.len();
+        self.aliases.push(None);
+        self.depths.push(0);
+        let (Some(id), Ok(canonical)) = (dir_id(path), fs::canonicalize(path)) else {
+            return vec![];
+        };
+        // find out the duplicate roots
+        if let Some(&first) = self.firsts.get(&id) {
+            self.aliases[idx] = Some(first);
+            return vec![];
+        }
+        self.firsts.insert(id, idx);
+        self.depths[idx] = canonical.components().count();
+
+        // find out the roots which have an ancestor in the roots,
+        // the files may be counted by the walk of their parent directories
+        let inner = Vec::from_iter(
+            self.canonicals
+                .range(canonical.clone()..)
+                .take_while(|(p, _)| p.starts_with(&canonical))
+                .map(|(p, &(i, id))| (p.clone(), i, id)),
+        );
+        for (p, i, id) in inner {
+            self.add_nested(&p, i, id);
+        }
+        let outer = Vec::from_iter(
+            canonical
+                .ancestors()
+                .skip(1)
+                .filter_map(|p| self.canonicals.get(p).map(|&(i, _)| i)),
+        );
+        if !outer.is_empty() {
+            self.add_nested(&canonical, idx, id);
+        }
+        self.canonicals.insert(canonical, (idx, id));
+        return outer;
+    }
+
+    // mark a root inside another root, it may be inside several ones
+    fn add_nested(&mut self, path: &Path, idx: usize, id: DirId) {
+        if path.is_dir() {
+            self.nested.insert(id, idx);
+        } else if let (Some(parent_id), Some(name)) = (path.parent().and_then(dir_id), path.file_name()) {
+            let files = self.inner_files.entry(parent_id).or_default();
+            if !files.iter().any(|&(i, _)| i == idx) {
+                files.push((idx, name.to_os_string()));
+            }
+        }
+    }
+
+    /// Whether the root is the same directory as a previous one, it's not traversed by itself
+    pub fn is_alias(&self, r_idx: usize) -> bool {
+        return self.aliases[r_idx].is_some();
+    }
+
+    /// Make the visit of a traversed directory of a root, and return the sub-directories to traverse.
+    /// The nested roots among the sub-directories are not traversed, but reached.
+    pub fn visit(&self, r_idx: usize, dirpath: &Path, detail: DirDetail, opts: &Options) -> (DirList, Visit) {
+        let (sub_dirs, cnt) = detail;
+        let mut visit = Visit {
+            r_idx,
+            cnt,
+            reached: vec![],
+            counted: vec![],
+        };
+        let mut to_traverse = DirList::new();
+        for path in sub_dirs.into_iter().filter(|_| opts.recursive) {
+            let nested = match self.nested.is_empty() {
+                true => None,
+                false => dir_id(&path).and_then(|id| self.nested.get(&id)),
+            };
+            match nested {
+                Some(&n_idx) => visit.reached.push(n_idx),
+                None => to_traverse.push(path),
+            }
+        }
+
+        // the regular files given as roots in this directory, which have been counted by the walk
+        let files = match self.inner_files.is_empty() {
+            true => None,
+            false => dir_id(dirpath).and_then(|id| self.inner_files.get(&id)),
+        };
+        for (f_idx, name) in files.into_iter().flatten() {
+            if is_counted_file(name, opts) {
+                visit.counted.push(*f_idx);
+            }
+        }
+        return (to_traverse, visit);
+    }
+
+    /// Sum up the visits into the counters of the roots, along with the counters of the regular files given as roots.
+    /// The nested roots are credited to the roots they were reached from unless `opts.exclude_nested`,
+    /// and the duplicate roots share the result of the first one.
+    pub fn sum_up<'a>(
+        &self,
+        roots: &[PathBuf],
+        visits: impl IntoIterator<Item = &'a Visit>,
+        files: Vec<(usize, Counter)>,
+        opts: &Options,
+    ) -> Vec<Counter> {
+        let mut counters = Vec::from_iter(roots.iter().map(|p| Counter::new(p, opts.with_size)));
+        let mut reached = vec![];
+        let mut counted = vec![];
+        for visit in visits {
+            counters[visit.r_idx].merge(&visit.cnt);
+            reached.extend(visit.reached.iter().map(|&n_idx| (n_idx, visit.r_idx)));
+            counted.extend(visit.counted.iter().copied());
+        }
+        for (r_idx, cnt) in files {
+            counters[r_idx] = cnt;
+        }
+        for f_idx in counted {
+            counters[f_idx].overlapped = true;
+        }
+
+        // credit the nested roots to the roots they were reached from, the deeper ones first
+        reached.sort_by_key(|&(n_idx, _)| Reverse(self.depths[n_idx]));
+        for (n_idx, r_idx) in reached {
+            if !opts.exclude_nested {
+                let nested = counters[n_idx].clone();
+                counters[r_idx].merge(&nested);
+                counters[n_idx].overlapped = true;
+            }
+        }
+
+        // the duplicate roots share the result of the first one
+        for (r_idx, alias) in self.aliases.iter().enumerate() {
+            if let Some(&first) = alias.as_ref() {
+                let dirpath = counters[r_idx].dirpath.clone();
+                counters[r_idx] = counters[first].clone();
+                counters[r_idx].dirpath = dirpath;
+                counters[r_idx].overlapped = true;
+            }
+        }
+        return counters;
+    }
+}
+
+/// Traverse the roots, return the counter of each valid root, the visits of the traversed
+/// directories, and whether all the roots are valid.
+/// The roots are traversed as they are read from `dirlist`, unless the found entries are displayed.
+/// The visits are empty unless `opts.keep_dirs`, the invalid roots are reported and skipped.
+pub fn parallel_walk(
+    dirlist: impl IntoIterator<Item = PathBuf>,
+    opts: Options,
+    n_thread: usize,
+) -> (Vec<Counter>, Vec<Visit>, bool) {
+    // each path is sent with the index and the path of the root it belongs to
+    let (path_tx, path_rx) = m_channel::<(usize, Arc<Path>, PathBuf)>();
+    let (visit_tx, visit_rx) = s_channel::<Visit>();
+    // the counters of the regular files given as roots, and the invalid roots
+    let (file_tx, file_rx) = s_channel::<(usize, Counter)>();
+    let (invalid_tx, invalid_rx) = s_channel::<usize>();
+    // the number of the sent paths which are not traversed yet
+    let pending = Arc::new(AtomicUsize::new(0));
+    let overlaps = Arc::new(RwLock::new(Overlaps::default()));
+    let opts = Arc::new(opts);
+
     // create walk threads which amount is n_thread
-    for t_idx in 0..n_thread {
+    for _ in 0..n_thread {
         // clone channels
         let _path_tx = path_tx.clone();
         let _path_rx = path_rx.clone();
-        let _cnt_tx = cnt_tx.clone();
-        let _lock = stat_locker.clone();
-        let _filter = filter.clone();
+        let _visit_tx = visit_tx.clone();
+        let _file_tx = file_tx.clone();
+        let _invalid_tx = invalid_tx.clone();
+        let _pending = pending.clone();
+        let _overlaps = overlaps.clone();
+        let _opts = opts.clone();
 
         // create walk threads
         thread::Builder::new()
             .spawn(move || {
                 // get a dir path to traverse
-                for dirpath in _path_rx {
-                    // switch stat to BUSY
-                    {
-                        let mut idle_stat = _lock.lock().expect("acquire lock err");
-                        idle_stat.insert(t_idx, false);
-                    }
-
-                    // traverse all files in the directory
-                    match walk(&dirpath, with_hidden, with_size, _filter.clone(), verbose) {
-                        Ok((sub_dirs, sub_cnt)) => {
-                            // send the sub_dirs and the sub_counter back
-                            for path in sub_dirs {
-                                _path_tx.send(path.clone()).expect("send path err");
-                            }
-                            _cnt_tx.send(sub_cnt).expect("send counter err");
-                        }
-                        Err(err) => op::print_err(&err, &dirpath.display()),
+                for (r_idx, root, dirpath) in _path_rx {
+                    // the roots are checked here rather than before the traversal,
+                    // and the regular files given as roots are counted directly
+                    let root_meta = match dirpath == *root {
+                        true => Some(fs::metadata(&dirpath)),
+                        false => None,
                     };
-
-                    // switch stat to IDLE
-                    {
-                        let mut idle_stat = _lock.lock().expect("acquire lock err");
-                        idle_stat.insert(t_idx, true);
+                    let is_root = root_meta.is_some();
+                    match root_meta {
+                        Some(Err(err)) => {
+                            op::print_err(&err, &dirpath.display());
+                            _invalid_tx.send(r_idx).expect("send root err");
+                        }
+                        Some(Ok(meta)) if !meta.is_dir() => {
+                            let cnt = Counter::from_file(&dirpath, &meta, &_opts);
+                            _file_tx.send((r_idx, cnt)).expect("send counter err");
+                        }
+                        // traverse all files in the directory
+                        _ => match walk_cached(&dirpath, &root, &_opts) {
+                            Ok(detail) => {
+                                // send the sub_dirs and the visit back
+                                let (sub_dirs, visit) = _overlaps
+                                    .read()
+                                    .expect("acquire lock err")
+                                    .visit(r_idx, &dirpath, detail, &_opts);
+                                _pending.fetch_add(sub_dirs.len(), Ordering::SeqCst);
+                                for path in sub_dirs {
+                                    _path_tx.send((r_idx, root.clone(), path)).expect("send path err");
+                                }
+                                _visit_tx.send(visit).expect("send counter err");
+                            }
+                            Err(err) => {
+                                op::print_err(&err, &dirpath.display());
+                                if is_root {
+                                    _invalid_tx.send(r_idx).expect("send root err");
+                                }
+                            }
+                        },
                     }
+
+                    // the sub-directories have been counted as pending before this one is done
+                    _pending.fetch_sub(1, Ordering::SeqCst);
                 }
             })
             .expect("create thread err");
     }
 
-    // check the status
-    loop {
-        let is_idle: bool;
-        {
-            let idle_stat = stat_locker.lock().expect("acquire lock err");
-            is_idle = idle_stat.values().all(|st| st == &true);
+    // wait until all the sent paths are traversed
+    let wait_idle = || {
+        while pending.load(Ordering::SeqCst) > 0 {
+            thread::sleep(Duration::from_millis(10));
         }
-
-        if is_idle && path_rx.is_empty() {
-            break;
-        } else {
-            thread::sleep(Duration::from_millis(100));
+    };
+    let send_root = |r_idx: usize, path: &Path| {
+        pending.fetch_add(1, Ordering::SeqCst);
+        path_tx.send((r_idx, Arc::from(path), path.to_path_buf())).expect("send path err");
+    };
+
+    // send the roots to path channel as they are read, the duplicate roots are traversed only once.
+    // the found entries are displayed only once, so all the roots are read before the traversal then.
+    let streamed = !(opts.verbose || opts.template.is_some());
+    let mut roots = vec![];
+    // the roots which may have traversed the roots read after them as their sub-directories
+    let mut stale = HashSet::new();
+    for path in dirlist {
+        let r_idx = roots.len();
+        let outer = overlaps.write().expect("acquire lock err").add(&path);
+        if streamed {
+            stale.extend(outer);
+            if !overlaps.read().expect("acquire lock err").is_alias(r_idx) {
+                send_root(r_idx, &path);
+            }
         }
+        roots.push(path);
+    }
+    if !streamed {
+        let overlaps = overlaps.read().expect("acquire lock err");
+        for (r_idx, path) in roots.iter().enumerate().filter(|(r_idx, _)| !overlaps.is_alias(*r_idx)) {
+            send_root(r_idx, path);
+        }
+    }
+    wait_idle();
+    let mut visits = Vec::from_iter(visit_rx.try_iter());
+    let mut invalid = HashSet::<usize>::from_iter(invalid_rx.try_iter());
+
+    // traverse the stale roots again, now that all the nested roots are known
+    stale.retain(|r_idx| !invalid.contains(r_idx));
+    if !stale.is_empty() {
+        visits.retain(|visit| !stale.contains(&visit.r_idx));
+        for &r_idx in &stale {
+            send_root(r_idx, &roots[r_idx]);
+        }
+        wait_idle();
+        visits.extend(visit_rx.try_iter());
+        invalid.extend(invalid_rx.try_iter());
     }
 
     // get the result
-    while let Ok(cnt) = cnt_rx.try_recv() {
-        for _cnt in &mut counters {
-            _cnt.merge(&cnt);
+    let overlaps = overlaps.read().expect("acquire lock err");
+    let files = Vec::from_iter(file_rx.try_iter());
+    let counters = overlaps.sum_up(&roots, &visits, files, &opts);
+
+    // the invalid roots have been reported, the aliases of them are invalid too
+    for (r_idx, alias) in overlaps.aliases.iter().enumerate() {
+        if alias.is_some_and(|first| invalid.contains(&first)) {
+            invalid.insert(r_idx);
         }
     }
-
-    return counters;
+    let all_valid = invalid.is_empty();
+    let counters = Vec::from_iter(
+        counters
+            .into_iter()
+            .enumerate()
+            .filter(|(r_idx, _)| !invalid.contains(r_idx))
+            .map(|(_, cnt)| cnt),
+    );
+    let visits = match opts.keep_dirs {
+        true => visits,
+        false => vec![],
+    };
+
+    return (counters, visits, all_valid);
 }
 
+#[cfg(test)]
+use crate::testutil::TempDir;
+
 #[test]
 fn test_readable_size() {
+    assert_eq!(Counter::add_unit_to_size(1023), "1023B");
+    assert_eq!(Counter::add_unit_to_size(1434), "1.4K");
+    assert_eq!(Counter::add_unit_to_size(15926), "15.6K");
+    assert_eq!(Counter::add_unit_to_size(53589793), "51.1M");
+    assert_eq!(Counter::add_unit_to_size(238462643383), "222.1G");
+    assert_eq!(Counter::add_unit_to_size(279502884197169), "254.2T");
+    assert_eq!(Counter::add_unit_to_size(0xffffffffffffffff), "16E");
+
     let mut c = Counter::new(&PathBuf::from("."), true);
+    c.add_size(1, 1, 1434);
+    assert_eq!(c.readable_size(), "1.4K");
+}
 
-    c.sz_map.as_mut().unwrap().insert(1, 1023);
-    assert_eq!(c.readable_size(), "1023B");
+#[test]
+fn test_counter_dirpath() {
+    assert_eq!(Counter::new(&PathBuf::from("data"), false).dirpath, "data/");
+    assert_eq!(Counter::new(&PathBuf::from("data2"), false).dirpath, "data2/");
+    assert_eq!(Counter::new(&PathBuf::from("/"), false).dirpath, "/");
+}
 
-    c.sz_map.as_mut().unwrap().insert(1, 1434);
-    assert_eq!(c.readable_size(), "1.4K");
+#[test]
+fn test_collapse() {
+    let make = |name: &str, n_files: u64, overlapped: bool| {
+        let mut c = crate::testutil::counter(name, n_files, 0);
+        c.overlapped = overlapped;
+        return c;
+    };
+    let counters = vec![
+        make("a", 5, false),
+        make("b", 4, false),
+        make("c", 3, false),
+        make("c", 3, true),
+        make("d", 2, false),
+    ];
+    let total = Counter::sum(&counters);
+
+    let rows = Counter::collapse(counters.clone(), Some(2));
+    assert_eq!(rows.len(), 3);
+    assert_eq!((rows[2].dirpath.as_str(), rows[2].collapsed), ("(3 others)", 3));
+    assert_eq!(rows[2].n_files, 5);
+    assert_eq!(Counter::sum(&rows), total);
+
+    assert_eq!(Counter::collapse(counters.clone(), Some(5)).len(), 5);
+    assert_eq!(Counter::collapse(counters, None).len(), 5);
+}
 
-    c.sz_map.as_mut().unwrap().insert(1, 15926);
-    assert_eq!(c.readable_size(), "15.6K");
+#[test]
+fn test_hard_links_dedup() {
+    let mut c1 = Counter::new(&PathBuf::from("."), true);
+    c1.add_size(1, 1, 100);
+    c1.add_size(2, 1, 100);
+    c1.add_size(3, 2, 1000);
+    c1.add_size(3, 2, 1000);
+    assert_eq!(c1.size(), 1200);
+    assert_eq!(c1.sz_links.len(), 1);
+
+    let mut c2 = Counter::new(&PathBuf::from("./sub"), true);
+    c2.add_size(3, 2, 1000);
+    c2.add_size(4, 1, 10);
+    c1.merge(&c2);
+    assert_eq!(c1.size(), 1210);
+
+    let mut c3 = Counter::new(&PathBuf::from("."), false);
+    c3.add_size(1, 1, 100);
+    assert_eq!(c3.size(), 0);
+}
 
-    c.sz_map.as_mut().unwrap().insert(1, 53589793);
-    assert_eq!(c.readable_size(), "51.1M");
+#[test]
+fn test_is_matched() {
+    let filter = Some(Regex::new("^__pycache__$").unwrap());
+    assert!(is_matched(&filter, OsStr::new("__pycache__")));
+    assert!(!is_matched(&filter, OsStr::new("src")));
+    assert!(is_matched(&None, OsStr::new("src")));
+}
 
-    c.sz_map.as_mut().unwrap().insert(1, 238462643383);
-    assert_eq!(c.readable_size(), "222.1G");
+#[test]
+fn test_dir_filter() {
+    let tmp = TempDir::new("dir-filter");
+    let root = tmp.path();
+    fs::create_dir_all(root.join("__pycache__/sub")).unwrap();
+    fs::create_dir_all(root.join("src/__pycache__")).unwrap();
+    fs::write(root.join("src/__pycache__/m.pyc"), "").unwrap();
+    fs::write(root.join("src/m.py"), "").unwrap();
+
+    let opts = Options {
+        dir_filter: Some(Regex::new("^__pycache__$").unwrap()),
+        ..Options::default()
+    };
+    // the unmatched dirs are not counted, but still descended into
+    let (sub_dirs, cnt) = walk(root, root, &opts).unwrap();
+    assert_eq!(cnt.n_dirs, 1);
+    assert_eq!(sub_dirs.len(), 2);
+
+    let (counters, _, _) = parallel_walk(vec![root.to_path_buf()], opts, 2);
+    assert_eq!((counters[0].n_files, counters[0].n_dirs), (2, 2));
+    let (counters, _, _) = parallel_walk(vec![root.to_path_buf()], Options::default(), 2);
+    assert_eq!((counters[0].n_files, counters[0].n_dirs), (2, 4));
+}
 
-    c.sz_map.as_mut().unwrap().insert(1, 279502884197169);
-    assert_eq!(c.readable_size(), "254.2T");
+#[test]
+fn test_list_only() {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::symlink;
+
+    let tmp = TempDir::new("list");
+    let root = tmp.path();
+    fs::create_dir_all(root.join("d.txt")).unwrap();
+    fs::write(root.join("a.txt"), "1").unwrap();
+    fs::write(root.join("b.log"), "2").unwrap();
+    symlink(root.join("a.txt"), root.join("la.txt")).unwrap();
+    symlink(root.join("b.log"), root.join("lb.log")).unwrap();
+    symlink(root.join("d.txt"), root.join("ld.txt")).unwrap();
+    fs::write(root.join(OsStr::from_bytes(b"bad\xffname.txt")), "").unwrap();
+
+    let opts = Options {
+        filter: Some(Regex::new(r"\.txt$").unwrap()),
+        template: Some(Template::parse("{path}\\0").unwrap()),
+        list_only: true,
+        ..Options::default()
+    };
+    let mut listing = vec![];
+    let (sub_dirs, cnt) = walk_into(root, root, &opts, &mut listing).unwrap();
+    assert!(listing.ends_with(b"\0"));
+    let mut paths = Vec::from_iter(listing.split(|&b| b == 0).filter(|p| !p.is_empty()));
+    paths.sort();
+    // the unmatched files, the symlinks to unmatched files and the dirs are not listed,
+    // and the non-UTF-8 names are listed as they are
+    let expected = [root.join("a.txt"), root.join(OsStr::from_bytes(b"bad\xffname.txt")), root.join("la.txt")];
+    assert_eq!(paths, Vec::from_iter(expected.iter().map(|p| p.as_os_str().as_bytes())));
+    assert_eq!(sub_dirs, [root.join("d.txt")]);
+    assert_eq!((cnt.n_files, cnt.n_dirs), (4, 2));
+}
 
-    c.sz_map.as_mut().unwrap().insert(1, 0xffffffffffffffff);
-    assert_eq!(c.readable_size(), "16E");
+#[test]
+fn test_overlapped_roots() {
+    let tmp = TempDir::new("overlap");
+    let root = tmp.path();
+    fs::create_dir_all(root.join("a/b")).unwrap();
+    fs::write(root.join("a/f1"), "1").unwrap();
+    fs::write(root.join("a/b/f2"), "2").unwrap();
+    fs::write(root.join("a/b/f3"), "3").unwrap();
+
+    let opts = |exclude_nested| Options {
+        exclude_nested,
+        ..Options::default()
+    };
+    let dirlist = vec![root.join("a"), root.join("a/b"), root.join("a/./b/")];
+
+    let (counters, _, _) = parallel_walk(dirlist.clone(), opts(false), 2);
+    assert_eq!(
+        Vec::from_iter(counters.iter().map(|c| (c.n_files, c.overlapped))),
+        vec![(3, false), (2, true), (2, true)]
+    );
+    assert_eq!(Counter::sum(&counters).0, 3);
+
+    let (counters, _, _) = parallel_walk(dirlist, opts(true), 2);
+    assert_eq!(
+        Vec::from_iter(counters.iter().map(|c| (c.n_files, c.overlapped))),
+        vec![(1, false), (2, false), (2, true)]
+    );
+    assert_eq!(Counter::sum(&counters).0, 3);
+}
+
+#[test]
+fn test_file_roots() {
+    let tmp = TempDir::new("files");
+    let root = tmp.path();
+    fs::create_dir_all(root.join("d")).unwrap();
+    fs::write(root.join("f1"), "12345").unwrap();
+    fs::write(root.join("d/f2"), "1").unwrap();
+
+    let opts = Options {
+        with_size: true,
+        apparent_size: true,
+        ..Options::default()
+    };
+    let dirlist = vec![root.join("f1"), root.join("d"), root.join("missing"), root.join("d/f2")];
+    let (counters, _, all_valid) = parallel_walk(dirlist, opts, 2);
+    // the missing root is reported and skipped
+    assert!(!all_valid);
+    assert_eq!(counters.len(), 3);
+    assert_eq!(counters[0].dirpath, root.join("f1").to_string_lossy());
+    assert_eq!((counters[0].n_files, counters[0].size()), (1, 5));
+    assert_eq!((counters[1].n_files, counters[1].size()), (1, 1));
+    assert!(counters[2].overlapped);
+    assert_eq!(Counter::sum(&counters), (2, 0, 6));
+
+    // the hidden file roots are counted since they are given explicitly,
+    // and they are overlapped only if the walk counted them
+    let rows = |counters: &[Counter]| Vec::from_iter(counters.iter().map(|c| (c.n_files, c.overlapped)));
+    fs::write(root.join("d/.hid"), "").unwrap();
+    let dirlist = vec![root.join("d"), root.join("d/.hid")];
+    let (counters, _, _) = parallel_walk(dirlist.clone(), Options::default(), 2);
+    assert_eq!(rows(&counters), [(1, false), (1, false)]);
+    assert_eq!(Counter::sum(&counters).0, 2);
+    let opts = Options {
+        with_hidden: true,
+        ..Options::default()
+    };
+    let (counters, _, _) = parallel_walk(dirlist, opts, 2);
+    assert_eq!(rows(&counters), [(2, false), (1, true)]);
+    assert_eq!(Counter::sum(&counters).0, 2);
+
+    let opts = Options {
+        filter: Some(Regex::new(r"\.txt$").unwrap()),
+        ..Options::default()
+    };
+    let (counters, _, _) = parallel_walk(vec![root.to_path_buf(), root.join("d/f2")], opts, 2);
+    assert_eq!(rows(&counters), [(0, false), (0, false)]);
+    let opts = Options {
+        exclude: Some(Regex::new(r"^\.hid$").unwrap()),
+        ..Options::default()
+    };
+    let (counters, _, _) = parallel_walk(vec![root.join("d/.hid")], opts, 2);
+    assert_eq!(rows(&counters), [(0, false)]);
 }
@@ -0,0 +1,299 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{self, ErrorKind, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+
+use crate::output;
+use crate::walker::{self, Counter, Options, Overlaps, Visit};
+
+/// The interval between two redraws, the events in it are handled together
+const TICK: Duration = Duration::from_millis(500);
+
+/// The counters of the watched roots, kept up to date by the inotify events
+struct Watcher {
+    inotify: Inotify,
+    opts: Options,
+    roots: Vec<PathBuf>,
+    overlaps: Overlaps,
+    // the regular files given as roots, they are checked on every tick
+    file_roots: Vec<usize>,
+    // the visit of each directory, with the index of the root it belongs to.
+    // it's ordered so that the directories inside a subtree are found in a range.
+    dirs: BTreeMap<PathBuf, Visit>,
+    watches: HashMap<WatchDescriptor, PathBuf>,
+    // the watch of each watched directory
+    wds: HashMap<PathBuf, WatchDescriptor>,
+    // the directories which can't be watched since the watch limit is exhausted.
+    // they are rescanned on every tick instead.
+    unwatched: HashSet<PathBuf>,
+}
+
+impl Watcher {
+    fn new(roots: Vec<PathBuf>, opts: Options) -> io::Result<Self> {
+        let overlaps = Overlaps::new(&roots);
+        let file_roots = Vec::from_iter((0..roots.len()).filter(|&r_idx| {
+            !overlaps.is_alias(r_idx) && fs::metadata(&roots[r_idx]).is_ok_and(|meta| !meta.is_dir())
+        }));
+        return Ok(Self {
+            inotify: Inotify::init()?,
+            opts,
+            roots,
+            overlaps,
+            file_roots,
+            dirs: BTreeMap::new(),
+            watches: HashMap::new(),
+            wds: HashMap::new(),
+            unwatched: HashSet::new(),
+        });
+    }
+
+    fn watch(&mut self, dir: &Path) {
+        let mask = WatchMask::CREATE
+            | WatchMask::DELETE
+            | WatchMask::MODIFY
+            | WatchMask::MOVED_FROM
+            | WatchMask::MOVED_TO
+            | WatchMask::ONLYDIR
+            | WatchMask::DONT_FOLLOW;
+        match self.inotify.watches().add(dir, mask) {
+            Ok(wd) => {
+                self.watches.insert(wd.clone(), dir.to_path_buf());
+                self.wds.insert(dir.to_path_buf(), wd);
+                self.unwatched.remove(dir);
+            }
+            // ENOSPC means the watch limit is exhausted
+            Err(err) if err.raw_os_error() == Some(libc::ENOSPC) => {
+                self.unwatched.insert(dir.to_path_buf());
+            }
+            // the directory is gone or unreadable, the rescan of its parent finds it out
+            Err(_) => {}
+        }
+    }
+
+    fn unwatch(&mut self, dir: &Path) {
+        self.unwatched.remove(dir);
+        if let Some(wd) = self.wds.remove(dir) {
+            self.watches.remove(&wd);
+            // the watch is already gone if the directory has been deleted
+            let _ = self.inotify.watches().remove(wd);
+        }
+    }
+
+    // the directories inside a subtree, including its top
+    fn subtree(&self, top: &Path) -> Vec<PathBuf> {
+        return Vec::from_iter(
+            self.dirs
+                .range(top.to_path_buf()..)
+                .take_while(|(dir, _)| dir.starts_with(top))
+                .map(|(dir, _)| dir.clone()),
+        );
+    }
+
+    // forget a subtree and remove its watches,
+    // the moved directories are watched again when their new parent is rescanned
+    fn remove_tree(&mut self, top: &Path) {
+        for dir in self.subtree(top) {
+            self.dirs.remove(&dir);
+            self.unwatch(&dir);
+        }
+        self.unwatch(top);
+    }
+
+    // scan a subtree of the root and watch each directory in it, the nested roots are left to themselves.
+    // each directory is watched before it's read, so the changes during the scan are not lost.
+    fn scan_tree(&mut self, top: &Path, r_idx: usize) {
+        let mut pending = vec![top.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            self.watch(&dir);
+            match walker::walk(&dir, &self.roots[r_idx], &self.opts) {
+                Ok(detail) => {
+                    let (sub_dirs, visit) = self.overlaps.visit(r_idx, &dir, detail, &self.opts);
+                    self.dirs.insert(dir, visit);
+                    pending.extend(sub_dirs);
+                }
+                Err(_) => self.unwatch(&dir),
+            }
+        }
+    }
+
+    // rescan a directory itself, its new sub-directories are scanned and the missing ones are removed
+    fn rescan_dir(&mut self, dir: &Path) {
+        let Some(r_idx) = self.dirs.get(dir).map(|visit| visit.r_idx) else {
+            return;
+        };
+        let Ok(detail) = walker::walk(dir, &self.roots[r_idx], &self.opts) else {
+            self.remove_tree(dir);
+            return;
+        };
+        // the nested roots are still there though they are not traversed from here
+        let all_subs = HashSet::<PathBuf>::from_iter(detail.0.iter().cloned());
+        let (sub_dirs, visit) = self.overlaps.visit(r_idx, dir, detail, &self.opts);
+        self.dirs.insert(dir.to_path_buf(), visit);
+        if !self.opts.recursive {
+            return;
+        }
+
+        let gone = Vec::from_iter(
+            self.subtree(dir)
+                .into_iter()
+                .filter(|d| d.parent() == Some(dir) && !all_subs.contains(d)),
+        );
+        for gone in gone {
+            self.remove_tree(&gone);
+        }
+        for sub_dir in sub_dirs {
+            if !self.dirs.contains_key(&sub_dir) {
+                self.scan_tree(&sub_dir, r_idx);
+            }
+        }
+    }
+
+    // scan everything at first, and rescan it when the events are lost
+    fn rescan_all(&mut self) {
+        for root in self.roots.clone() {
+            self.remove_tree(&root);
+        }
+        for r_idx in 0..self.roots.len() {
+            if !self.overlaps.is_alias(r_idx) && !self.file_roots.contains(&r_idx) {
+                self.scan_tree(&self.roots[r_idx].clone(), r_idx);
+            }
+        }
+    }
+
+    // read the pending events, return the directories to rescan, and whether the queue overflowed
+    fn read_events(&mut self) -> io::Result<(HashSet<PathBuf>, bool)> {
+        let mut buffer = [0_u8; 4096];
+        let mut dirty = HashSet::new();
+        let mut overflow = false;
+        loop {
+            let events = match self.inotify.read_events(&mut buffer) {
+                Ok(events) => events,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            };
+            let mut n_events = 0;
+            for event in events {
+                n_events += 1;
+                if event.mask.contains(EventMask::Q_OVERFLOW) {
+                    overflow = true;
+                } else if event.mask.contains(EventMask::IGNORED) {
+                    if let Some(dir) = self.watches.remove(&event.wd) {
+                        self.wds.remove(&dir);
+                    }
+                } else if let Some(dir) = self.watches.get(&event.wd) {
+                    dirty.insert(dir.clone());
+                }
+            }
+            if n_events == 0 {
+                break;
+            }
+        }
+        return Ok((dirty, overflow));
+    }
+
+    // sum up the visits into the counters of the roots the same way as the walk
+    fn counters(&self) -> Vec<Counter> {
+        let mut files = vec![];
+        for &r_idx in &self.file_roots {
+            let path = &self.roots[r_idx];
+            if let Ok(meta) = fs::metadata(path) {
+                files.push((r_idx, Counter::from_file(path, &meta, &self.opts)));
+            }
+        }
+        return self.overlaps.sum_up(&self.roots, self.dirs.values(), files, &self.opts);
+    }
+}
+
+/// Count the roots, then keep the counters up to date and redraw them by `render` on changes.
+/// It runs until the process is interrupted.
+pub fn run(roots: Vec<PathBuf>, opts: Options, render: impl Fn(Vec<Counter>) -> String) -> io::Result<()> {
+    // the invalid roots are reported and skipped
+    let roots = Vec::from_iter(roots.into_iter().filter(|root| match fs::metadata(root) {
+        Ok(_) => true,
+        Err(err) => {
+            output::print_err(&err, &root.display());
+            false
+        }
+    }));
+    let mut watcher = Watcher::new(roots, opts)?;
+    watcher.rescan_all();
+
+    // the output is redrawn in place on a terminal, and appended otherwise
+    let clear = match io::stdout().is_terminal() {
+        true => "\x1b[H\x1b[2J",
+        false => "",
+    };
+    let mut last = String::new();
+    loop {
+        let (mut dirty, overflow) = watcher.read_events()?;
+        if overflow {
+            watcher.rescan_all();
+        } else {
+            dirty.extend(watcher.unwatched.iter().cloned());
+            for dir in dirty {
+                watcher.rescan_dir(&dir);
+            }
+        }
+
+        let output = render(watcher.counters());
+        if output != last {
+            println!("{}{}", clear, output);
+            last = output;
+        }
+        thread::sleep(TICK);
+    }
+}
+
+#[test]
+fn test_watcher() {
+    let tmp = crate::testutil::TempDir::new("watch");
+    let root = tmp.path();
+    fs::create_dir_all(root.join("a/b")).unwrap();
+    fs::write(root.join("a/f1"), "1").unwrap();
+
+    let opts = Options {
+        keep_dirs: true,
+        ..Options::default()
+    };
+    let mut watcher = Watcher::new(vec![root.to_path_buf()], opts).unwrap();
+    watcher.scan_tree(root, 0);
+    assert_eq!(watcher.counters()[0].n_files, 1);
+    assert_eq!(watcher.watches.len(), 3);
+
+    fs::write(root.join("a/b/f2"), "2").unwrap();
+    fs::create_dir_all(root.join("a/c/d")).unwrap();
+    fs::write(root.join("a/c/d/f3"), "3").unwrap();
+    fs::remove_file(root.join("a/f1")).unwrap();
+    let (dirty, overflow) = watcher.read_events().unwrap();
+    assert!(!overflow);
+    assert_eq!(dirty, HashSet::from([root.join("a"), root.join("a/b")]));
+    for dir in dirty {
+        watcher.rescan_dir(&dir);
+    }
+    let cnt = &watcher.counters()[0];
+    assert_eq!((cnt.n_files, cnt.n_dirs), (2, 4));
+    assert_eq!(watcher.watches.len(), 5);
+
+    fs::remove_dir_all(root.join("a/c")).unwrap();
+    watcher.rescan_dir(&root.join("a"));
+    assert_eq!(watcher.counters()[0].n_files, 1);
+    assert_eq!(watcher.dirs.len(), 3);
+    assert_eq!((watcher.watches.len(), watcher.wds.len()), (3, 3));
+
+    // the nested root is credited to the root containing it, and overlapped
+    let opts = Options {
+        keep_dirs: true,
+        ..Options::default()
+    };
+    let mut watcher = Watcher::new(vec![root.to_path_buf(), root.join("a")], opts).unwrap();
+    watcher.scan_tree(root, 0);
+    watcher.scan_tree(&root.join("a"), 1);
+    assert_eq!(watcher.dirs.len(), 3);
+    let counters = watcher.counters();
+    assert_eq!((counters[0].n_files, counters[0].n_dirs), (1, 2));
+    assert_eq!((counters[1].n_files, counters[1].overlapped), (1, true));
+}